@@ -0,0 +1,4 @@
+fn main() {
+    // The built-in templates are embedded with `include_dir!`
+    println!("cargo:rerun-if-changed=built_in");
+}
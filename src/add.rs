@@ -0,0 +1,76 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// How a rendered file relates to what is already in the target directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileStatus {
+    New,
+    /// The existing file already has the rendered contents
+    Unchanged,
+    /// The existing file has different contents
+    Conflict,
+}
+
+/// Compares rendered files against an existing directory, without writing
+/// anything.
+pub fn plan(
+    target_dir: &Path,
+    rendered: &BTreeMap<PathBuf, String>,
+) -> Result<Vec<(PathBuf, FileStatus)>> {
+    let mut plan = Vec::new();
+    for (rel_path, contents) in rendered {
+        let path = target_dir.join(rel_path);
+        let status = if !path.exists() {
+            FileStatus::New
+        } else if std::fs::read(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            == contents.as_bytes()
+        {
+            FileStatus::Unchanged
+        } else {
+            FileStatus::Conflict
+        };
+        plan.push((rel_path.clone(), status));
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_rendered_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join(".github/workflows")).unwrap();
+        std::fs::write(dir.path().join(".github/workflows/ci.yml"), "old\n").unwrap();
+        std::fs::write(dir.path().join("README.md"), "same\n").unwrap();
+        let rendered = BTreeMap::from([
+            (
+                PathBuf::from(".github/workflows/ci.yml"),
+                "new\n".to_string(),
+            ),
+            (
+                PathBuf::from(".github/workflows/release.yml"),
+                "".to_string(),
+            ),
+            (PathBuf::from("README.md"), "same\n".to_string()),
+        ]);
+
+        assert_eq!(
+            plan(dir.path(), &rendered).unwrap(),
+            vec![
+                (
+                    PathBuf::from(".github/workflows/ci.yml"),
+                    FileStatus::Conflict
+                ),
+                (
+                    PathBuf::from(".github/workflows/release.yml"),
+                    FileStatus::New
+                ),
+                (PathBuf::from("README.md"), FileStatus::Unchanged),
+            ]
+        );
+    }
+}
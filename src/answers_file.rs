@@ -0,0 +1,267 @@
+use crate::config::TemplateConfig;
+use crate::env::{HOST_NAMESPACE, HostInfo, canonical_env_key};
+use crate::git;
+use crate::templating;
+use anyhow::{Context, Result, anyhow};
+use chrono::NaiveDate;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tera::Context as TeraContext;
+
+pub const ANSWERS_FILE: &str = ".hayaku-answers.toml";
+
+/// A record of how a project was generated, written to `.hayaku-answers.toml`
+/// in the new project.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnswersFile {
+    pub hayaku_version: String,
+    pub project_name: String,
+    pub template: TemplateSource,
+    /// The machine and run variables the project was generated with, so that
+    /// `hayaku update` renders the old template the same way
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<RecordedHost>,
+    /// Prompted values keyed by their names in `hayaku.toml`. Secret values
+    /// are left out.
+    #[serde(default)]
+    pub answers: toml::Table,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateSource {
+    /// The id the template was selected with, e.g. `rust` or `local/rust`
+    pub id: String,
+    /// The template directory
+    pub path: PathBuf,
+    pub version: Option<String>,
+    /// The `origin` remote when the template lives in a git repository
+    pub repository: Option<String>,
+    /// The checked out commit when the template lives in a git repository
+    pub commit: Option<String>,
+    /// Whether the template had uncommitted changes, so that `commit` doesn't
+    /// match what the project was generated from
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dirty: bool,
+}
+
+/// The values from the `HAYAKU` table that change from run to run. The user
+/// and host name are only recorded when the template uses them, so that they
+/// don't end up in every project's repository.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedHost {
+    pub date: NaiveDate,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+}
+
+impl RecordedHost {
+    fn from_context(context: &TeraContext, template_path: &Path) -> Option<Self> {
+        let host = context.get(HOST_NAMESPACE)?;
+        let used = |field: &str| {
+            references_host_field(template_path, field)
+                .then(|| host[field].as_str().map(str::to_string))
+                .flatten()
+        };
+        Some(Self {
+            date: NaiveDate::parse_from_str(host["date"].as_str()?, "%Y-%m-%d").ok()?,
+            user: used("user"),
+            hostname: used("hostname"),
+        })
+    }
+
+    /// Replaces the recorded values in `host`. Values that weren't recorded
+    /// keep their current ones.
+    pub fn apply_to(&self, host: &mut HostInfo) {
+        host.date = self.date;
+        if let Some(user) = &self.user {
+            host.user = user.clone();
+        }
+        if let Some(hostname) = &self.hostname {
+            host.hostname = hostname.clone();
+        }
+    }
+}
+
+/// Whether any file of the template at `template_path`, or its `hayaku.toml`,
+/// refers to `HAYAKU.<field>`.
+fn references_host_field(template_path: &Path, field: &str) -> bool {
+    let pattern = Regex::new(&format!(
+        r#"\b{HOST_NAMESPACE}\s*(\.\s*{field}\b|\[\s*["']{field}["']\s*\])"#
+    ))
+    .expect("the pattern is valid");
+    let Ok(walker) = templating::template_walker(template_path) else {
+        return false;
+    };
+    walker
+        .build()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.into_path())
+        .chain([template_path.join("hayaku.toml")])
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .any(|contents| pattern.is_match(&contents))
+}
+
+/// The values of `config`'s variables in a rendered context, keyed by their
+/// names in `hayaku.toml`.
+pub fn collect_answers(
+    config: &TemplateConfig,
+    context: &TeraContext,
+    include_secrets: bool,
+) -> Result<toml::Table> {
+    let mut answers = toml::Table::new();
+    for (raw_key, env_cfg) in config.env.iter() {
+        if env_cfg.is_secret() && !include_secrets {
+            continue;
+        }
+        if let Some(value) = context.get(&canonical_env_key(raw_key)) {
+            let value = toml::Value::try_from(value)
+                .map_err(|err| anyhow!("Failed to record answer for {raw_key}:\n{err}"))?;
+            answers.insert(raw_key.clone(), value);
+        }
+    }
+    Ok(answers)
+}
+
+impl AnswersFile {
+    /// Collects the answers to `config`'s variables from a rendered context.
+    pub fn new(
+        id: &str,
+        template_path: &Path,
+        config: &TemplateConfig,
+        project_name: &str,
+        context: &TeraContext,
+    ) -> Result<Self> {
+        let answers = collect_answers(config, context, false)?;
+        let path = template_path
+            .canonicalize()
+            .unwrap_or_else(|_| template_path.to_path_buf());
+        Ok(Self {
+            hayaku_version: env!("CARGO_PKG_VERSION").to_string(),
+            project_name: project_name.to_string(),
+            template: TemplateSource {
+                id: id.to_string(),
+                version: config.version.as_ref().map(|version| version.to_string()),
+                repository: git::origin_url(&path),
+                commit: git::head_commit(&path),
+                dirty: git::is_dirty(&path).unwrap_or(false),
+                path,
+            },
+            host: RecordedHost::from_context(context, template_path),
+            answers,
+        })
+    }
+
+    pub fn write_to_dir(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(ANSWERS_FILE);
+        let toml_string = toml::to_string_pretty(self)
+            .map_err(|err| anyhow!("Failed to serialize answers to TOML:\n{err}"))?;
+        std::fs::write(&path, toml_string)
+            .with_context(|| format!("Failed to write answers file {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn try_from_dir(dir: &Path) -> Result<Self> {
+        let path = dir.join(ANSWERS_FILE);
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read answers file {}", path.display()))?;
+        toml::from_str(&content)
+            .map_err(|err| anyhow!("Failed to parse answers file {}:\n{err}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::{self, Answers, HostInfo};
+    use crate::settings::HayakuSettings;
+
+    #[test]
+    fn records_answers_without_secrets() {
+        let template = tempfile::tempdir().unwrap();
+        std::fs::write(
+            template.path().join("hayaku.toml"),
+            "[template]\nname = \"svc\"\nversion = \"0.2.0\"\n\n[env.port]\ntype = \"string\"\nprompt = \"?\"\n\n[env.token]\ntype = \"string\"\nprompt = \"?\"\nsecret = true\n\n[env.docker]\ntype = \"bool\"\nprompt = \"?\"\n",
+        )
+        .unwrap();
+        let config = TemplateConfig::try_from_dir(template.path()).unwrap();
+        let answers: toml::Table = toml::from_str("port = \"8080\"\ntoken = \"hunter2\"").unwrap();
+        let host = HostInfo {
+            date: NaiveDate::from_ymd_opt(2024, 3, 9).unwrap(),
+            user: "ada".to_string(),
+            hostname: "ada-laptop".to_string(),
+            ..Default::default()
+        };
+        let context = env::build_context(
+            "demo",
+            &config,
+            &HayakuSettings::default(),
+            &Answers::fixed(answers),
+            &host,
+        )
+        .unwrap();
+
+        let file = AnswersFile::new("svc", template.path(), &config, "demo", &context).unwrap();
+        let project = tempfile::tempdir().unwrap();
+        file.write_to_dir(project.path()).unwrap();
+        let loaded = AnswersFile::try_from_dir(project.path()).unwrap();
+
+        assert_eq!(loaded, file);
+        assert_eq!(loaded.template.version.as_deref(), Some("0.2.0"));
+        assert_eq!(
+            loaded.answers,
+            toml::from_str::<toml::Table>("port = \"8080\"\ndocker = false").unwrap()
+        );
+
+        let mut redetected = HostInfo {
+            user: "grace".to_string(),
+            ..Default::default()
+        };
+        loaded.host.unwrap().apply_to(&mut redetected);
+        assert_eq!(redetected.date, host.date);
+        assert_eq!(redetected.user, "grace");
+    }
+
+    #[test]
+    fn records_the_user_and_host_name_only_when_the_template_uses_them() {
+        let template = tempfile::tempdir().unwrap();
+        std::fs::write(
+            template.path().join("hayaku.toml"),
+            "[template]\nname = \"svc\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            template.path().join("LICENSE.tera"),
+            "Copyright {{ HAYAKU.year }} {{ HAYAKU[\"user\"] }}\n",
+        )
+        .unwrap();
+        let config = TemplateConfig::try_from_dir(template.path()).unwrap();
+        let host = HostInfo {
+            date: NaiveDate::from_ymd_opt(2024, 3, 9).unwrap(),
+            user: "ada".to_string(),
+            hostname: "ada-laptop".to_string(),
+            ..Default::default()
+        };
+        let context = env::build_context(
+            "demo",
+            &config,
+            &HayakuSettings::default(),
+            &Answers::interactive(),
+            &host,
+        )
+        .unwrap();
+
+        let file = AnswersFile::new("svc", template.path(), &config, "demo", &context).unwrap();
+
+        assert_eq!(
+            file.host,
+            Some(RecordedHost {
+                date: host.date,
+                user: Some("ada".to_string()),
+                hostname: None,
+            })
+        );
+    }
+}
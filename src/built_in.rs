@@ -0,0 +1,115 @@
+use crate::config::TemplateConfig;
+use anyhow::{Context, Result};
+use include_dir::{Dir, DirEntry, include_dir};
+use std::path::{Path, PathBuf};
+
+static BUILT_IN_TEMPLATES: Dir<'static> = include_dir!("$CARGO_MANIFEST_DIR/built_in");
+
+const PREFIX: &str = "built_in-";
+
+/// FNV-1a over every embedded path and file, so that a rebuilt binary with
+/// changed templates never reuses a stale extraction.
+fn content_hash() -> u64 {
+    fn visit(entries: &[DirEntry], hash: &mut u64) {
+        for entry in entries {
+            let path = entry.path().to_string_lossy();
+            let contents = entry.as_file().map(|file| file.contents()).unwrap_or(&[]);
+            for byte in path.bytes().chain([0]).chain(contents.iter().copied()) {
+                *hash ^= u64::from(byte);
+                *hash = hash.wrapping_mul(0x100000001b3);
+            }
+            visit(entry.children(), hash);
+        }
+    }
+    let mut hash = 0xcbf29ce484222325;
+    visit(BUILT_IN_TEMPLATES.entries(), &mut hash);
+    hash
+}
+
+/// The versioned directory inside `cache_dir` that the built-in templates are
+/// extracted to. Its name changes whenever the embedded templates do.
+pub fn dir_in(cache_dir: &Path) -> PathBuf {
+    cache_dir.join(format!(
+        "{PREFIX}{}-{:016x}",
+        env!("CARGO_PKG_VERSION"),
+        content_hash()
+    ))
+}
+
+/// The config of every built-in template, read from the binary, along with the
+/// directory below `dir` it is extracted to.
+pub fn configs(dir: &Path) -> Result<Vec<(PathBuf, TemplateConfig)>> {
+    let mut configs = Vec::new();
+    for template in BUILT_IN_TEMPLATES.dirs() {
+        let path = dir.join(template.path());
+        let config = match template.get_file(template.path().join("hayaku.toml")) {
+            Some(file) => {
+                let contents = file
+                    .contents_utf8()
+                    .context("Built-in template config is not UTF-8")?;
+                TemplateConfig::parse(contents, &path.join("hayaku.toml"))?
+            }
+            None => TemplateConfig::default(&template.path().to_string_lossy()),
+        };
+        configs.push((path, config));
+    }
+    configs.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(configs)
+}
+
+/// Writes the built-in templates that are embedded in the binary to
+/// [`dir_in`] `cache_dir` and returns its path. An existing extraction for the
+/// same version is reused, and older ones are removed.
+pub fn extract_to(cache_dir: &Path) -> Result<PathBuf> {
+    let dest = dir_in(cache_dir);
+    if dest.is_dir() {
+        return Ok(dest);
+    }
+
+    std::fs::create_dir_all(cache_dir)
+        .with_context(|| format!("Failed to create cache directory {}", cache_dir.display()))?;
+    // Extract next to the destination first so that an interrupted extraction
+    // is never mistaken for a complete one
+    let staging = tempfile::tempdir_in(cache_dir)?;
+    BUILT_IN_TEMPLATES
+        .extract(staging.path())
+        .context("Failed to extract built-in templates")?;
+    if let Err(err) = std::fs::rename(staging.path(), &dest) {
+        // Another hayaku process may have finished extracting first
+        if !dest.is_dir() {
+            return Err(err).with_context(|| {
+                format!("Failed to extract built-in templates to {}", dest.display())
+            });
+        }
+    }
+
+    for entry in std::fs::read_dir(cache_dir)?.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(PREFIX) && entry.path() != dest {
+            let _ = std::fs::remove_dir_all(entry.path());
+        }
+    }
+
+    Ok(dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_once_and_prunes_old_versions() {
+        let cache = tempfile::tempdir().unwrap();
+        let stale = cache.path().join("built_in-0.0.0-0000000000000000");
+        std::fs::create_dir_all(&stale).unwrap();
+
+        let dir = extract_to(cache.path()).unwrap();
+        assert!(dir.join("rust/hayaku.toml").is_file());
+        assert!(!stale.exists());
+
+        std::fs::write(dir.join("marker"), "").unwrap();
+        assert_eq!(extract_to(cache.path()).unwrap(), dir);
+        assert!(dir.join("marker").exists());
+    }
+}
@@ -1,13 +1,22 @@
-use crate::config::TemplateConfig;
-use crate::env;
-use crate::hayaku_context::TemplateOrigin;
+use crate::Hayaku;
+use crate::add::{self, FileStatus};
+use crate::answers_file::{self, AnswersFile};
+use crate::config::{ConfigToml, TemplateConfig, TemplateSection};
+use crate::env::{self, EnvVarConfig};
+use crate::extract::{self, SubstitutionLocation};
+use crate::git;
+use crate::hayaku_context::{TemplateEntry, TemplateOrigin};
+use crate::listing::TemplateListing;
+use crate::new_template;
+use crate::settings::{self, HayakuSettings, resolve_settings_path};
+use crate::template_test::{self, FileDiff};
 use crate::templating;
-use crate::{Hayaku, hayaku_context::HayakuSettings};
+use crate::update::{self, FileUpdate};
+use crate::validate;
 use anyhow::{Result, anyhow, bail};
-use clap::{Parser, Subcommand, ValueEnum, command};
-use cliclack;
+use clap::{Parser, Subcommand, ValueEnum};
 use owo_colors::OwoColorize;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -19,6 +28,11 @@ use std::path::PathBuf;
 pub struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// The settings profile to use instead of one matched by the project's
+    /// path. Can also be set with HAYAKU_PROFILE.
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -27,13 +41,43 @@ enum Commands {
     Create(CreateOptions),
 
     #[command(name = "list", about = "List available templates")]
-    List,
+    List(ListOptions),
+
+    #[command(name = "add", about = "Add a template's files to an existing project")]
+    Add(AddOptions),
+
+    #[command(
+        name = "update",
+        about = "Apply changes to a template to a project created from it"
+    )]
+    Update(UpdateOptions),
+
+    #[command(name = "info", about = "Show what a template asks for and creates")]
+    Info(InfoOptions),
 
     #[command(name = "edit", about = "Edit templates")]
-    Edit,
+    Edit(EditOptions),
 
     #[command(name = "init", about = "Set up hayaku")]
     Init,
+
+    #[command(name = "config", about = "Show or change settings")]
+    Config(ConfigOptions),
+
+    #[command(name = "extract", about = "Turn an existing project into a template")]
+    Extract(ExtractOptions),
+
+    #[command(name = "new-template", about = "Create a new local template")]
+    NewTemplate(NewTemplateOptions),
+
+    #[command(name = "validate", about = "Check templates for problems")]
+    Validate(ValidateOptions),
+
+    #[command(
+        name = "test",
+        about = "Render a template's fixtures and compare them to snapshots"
+    )]
+    Test(TestOptions),
 }
 
 #[derive(ValueEnum, Clone, Debug, Default)]
@@ -62,6 +106,210 @@ pub struct CreateOptions {
     /// Overwrite existing files in the destination directory
     #[arg(short, long)]
     force: bool,
+
+    /// Only offer templates with this tag
+    #[arg(long, value_name = "TAG")]
+    tag: Vec<String>,
+
+    /// Don't write a .hayaku-answers.toml file into the new project
+    #[arg(long)]
+    no_answers_file: bool,
+
+    /// The name used for PROJECT_NAME and its variants. Defaults to the name
+    /// of the project directory.
+    #[arg(short, long)]
+    name: Option<String>,
+
+    #[command(flatten)]
+    answers: AnswerOptions,
+}
+
+#[derive(Parser, Debug)]
+pub struct AnswerOptions {
+    /// Answer a prompt, e.g. `--var crate_type=lib`
+    #[arg(long = "var", value_name = "KEY=VALUE", value_parser = env::parse_var_arg)]
+    vars: Vec<(String, String)>,
+
+    /// Answer prompts from a TOML file, such as a project's .hayaku-answers.toml
+    #[arg(long, value_name = "FILE")]
+    answers: Option<PathBuf>,
+}
+
+impl AnswerOptions {
+    /// Answers from `--var` and `--answers`, prompting for the rest.
+    fn to_answers(&self) -> Result<env::Answers> {
+        let mut answers = env::Answers::interactive().with_vars(self.vars.clone());
+        if let Some(path) = &self.answers {
+            let content = std::fs::read_to_string(path)
+                .map_err(|err| anyhow!("Failed to read {}:\n{err}", path.display()))?;
+            let mut table: toml::Table = toml::from_str(&content)
+                .map_err(|err| anyhow!("Failed to parse {}:\n{err}", path.display()))?;
+            // A .hayaku-answers.toml keeps its answers in an `answers` table
+            if let Some(toml::Value::Table(recorded)) = table.remove("answers")
+                && table.contains_key("template")
+            {
+                table = recorded;
+            }
+            answers = answers.with_values(table);
+        }
+        Ok(answers)
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct ListOptions {
+    /// Only list templates with this tag
+    #[arg(long, value_name = "TAG")]
+    tag: Vec<String>,
+
+    /// How to print the templates
+    #[arg(long, value_enum, default_value_t)]
+    format: ListFormat,
+}
+
+#[derive(Parser, Debug)]
+pub struct AddOptions {
+    /// The template to add, e.g. `github-ci` or `local/component`
+    #[arg(value_name = "TEMPLATE")]
+    template: String,
+
+    /// The directory to add the files to
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    into: PathBuf,
+
+    /// The name used for PROJECT_NAME and its variants. Prompted for if
+    /// omitted.
+    #[arg(short, long)]
+    name: Option<String>,
+
+    #[command(flatten)]
+    answers: AnswerOptions,
+}
+
+#[derive(Parser, Debug)]
+pub struct UpdateOptions {
+    /// The project to update
+    #[arg(value_name = "PROJECT_DIR", default_value = ".")]
+    project_dir: PathBuf,
+
+    /// The template version the project was created from. Defaults to the
+    /// commit recorded in the project's .hayaku-answers.toml.
+    #[arg(long, value_name = "DIR")]
+    base_template: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+pub struct EditOptions {
+    /// The template to edit. Opens the hayaku directory if omitted.
+    #[arg(value_name = "TEMPLATE")]
+    template: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct InfoOptions {
+    /// The template to describe, e.g. `rust` or `local/rust`
+    #[arg(value_name = "TEMPLATE")]
+    template: String,
+}
+
+#[derive(Parser, Debug)]
+pub struct ConfigOptions {
+    #[command(subcommand)]
+    command: ConfigCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// List every setting that is set, merged from the system, user and
+    /// project settings files
+    List {
+        /// Show the file each value comes from
+        #[arg(long)]
+        show_origin: bool,
+    },
+    /// Print a setting, e.g. `editor` or `global_env.company.name`
+    Get {
+        #[arg(value_name = "KEY")]
+        key: String,
+    },
+    /// Change a setting. The value is read as TOML, e.g. `true` or `["a"]`,
+    /// or as a string if it isn't valid TOML.
+    Set {
+        #[arg(value_name = "KEY")]
+        key: String,
+        #[arg(value_name = "VALUE")]
+        value: String,
+    },
+    /// Remove a setting
+    Unset {
+        #[arg(value_name = "KEY")]
+        key: String,
+    },
+    /// Open the settings file in your editor
+    Edit,
+    /// Print the path of the settings file
+    Path,
+}
+
+#[derive(ValueEnum, Clone, Debug, Default)]
+pub enum ListFormat {
+    #[default]
+    Text,
+    Json,
+    Toml,
+}
+
+#[derive(Parser, Debug)]
+pub struct ExtractOptions {
+    /// The project directory to turn into a template
+    #[arg(value_name = "PROJECT_DIR")]
+    project_dir: PathBuf,
+
+    /// The name of the new template
+    #[arg(short, long)]
+    name: String,
+
+    /// The project name to replace with template variables.
+    /// Defaults to the name of the project directory.
+    #[arg(long)]
+    project_name: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct NewTemplateOptions {
+    /// The name of the new template
+    #[arg(value_name = "NAME")]
+    name: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct ValidateOptions {
+    /// The template to validate
+    #[arg(value_name = "TEMPLATE", conflicts_with_all = ["all", "template_dir"])]
+    template: Option<String>,
+
+    /// Validate every available template
+    #[arg(long, conflicts_with_all = ["template_dir"])]
+    all: bool,
+
+    /// A directory containing a hayaku template
+    #[arg(long)]
+    template_dir: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+pub struct TestOptions {
+    /// The template to test
+    #[arg(value_name = "TEMPLATE", conflicts_with_all = ["template_dir"])]
+    template: Option<String>,
+
+    /// A directory containing a hayaku template
+    #[arg(long)]
+    template_dir: Option<PathBuf>,
+
+    /// Accept the rendered output as the new snapshots
+    #[arg(long)]
+    update: bool,
 }
 
 // fn validate_github_repo(repo: &str) -> Result<()> {
@@ -71,26 +319,86 @@ pub struct CreateOptions {
 //     Ok(())
 // }
 
-fn validate_directory(path: &PathBuf) -> Result<()> {
+fn validate_directory(path: &Path) -> Result<()> {
     if !path.is_dir() {
         bail!("The path {} is not a directory", path.display());
     }
     Ok(())
 }
 
-fn create(create_options: &CreateOptions) -> Result<()> {
-    let hayaku = Hayaku::try_new()?;
+fn is_empty_dir(path: &Path) -> bool {
+    path.read_dir()
+        .is_ok_and(|mut entries| entries.next().is_none())
+}
 
-    let template_message = if hayaku.no_local_templates() {
-        "No local templates; using built-in templates only".to_string()
-    } else {
-        format!(
-            "Loaded templates from {}",
-            hayaku.local_template_dir().display()
-        )
-    };
+/// Removes everything in a directory but keeps the directory itself, which
+/// may be the current directory.
+fn clear_directory(path: &Path) -> Result<()> {
+    if !path.is_dir() {
+        std::fs::remove_file(path)?;
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(path)? {
+        let entry_path = entry?.path();
+        if entry_path.is_dir() && !entry_path.is_symlink() {
+            std::fs::remove_dir_all(&entry_path)?;
+        } else {
+            std::fs::remove_file(&entry_path)?;
+        }
+    }
+    Ok(())
+}
+
+/// The absolute path of an existing file, with symlinks and `.` resolved, so
+/// that `.` is shown as the directory it stands for.
+fn resolved_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Whether a project path is just a name, like `foo`, rather than a path.
+fn is_bare_name(path: &str) -> bool {
+    let mut components = Path::new(path).components();
+    matches!(components.next(), Some(std::path::Component::Normal(_)))
+        && components.next().is_none()
+}
+
+/// The profile given with `--profile` or `HAYAKU_PROFILE`.
+fn selected_profile(profile: Option<&str>) -> Option<String> {
+    profile.map(str::to_string).or_else(|| {
+        std::env::var(settings::PROFILE_ENV_VAR)
+            .ok()
+            .filter(|name| !name.is_empty())
+    })
+}
+
+/// Applies the selected profile, or else the first profile whose `match`
+/// rules cover `dest_dir`.
+fn apply_profile(
+    hayaku: &Hayaku,
+    settings: HayakuSettings,
+    profile: Option<&str>,
+    dest_dir: &Path,
+) -> Result<HayakuSettings> {
+    if let Some(name) = selected_profile(profile) {
+        return settings.with_profile(&name);
+    }
+    match settings.matching_profile(hayaku.hayaku_dir(), dest_dir)? {
+        Some(name) => {
+            cliclack::log::info(format!("Using the {} profile", name.bold()))?;
+            settings.with_profile(&name)
+        }
+        None => Ok(settings),
+    }
+}
 
-    cliclack::log::info(format!("hayaku!\n{}", template_message.dimmed()))?;
+fn create(hayaku: &Hayaku, create_options: &CreateOptions, profile: Option<&str>) -> Result<()> {
+    cliclack::log::info("hayaku!")?;
+    // A selected profile can change `projects_dir`, so it applies before the
+    // destination is known
+    let mut settings = hayaku.parse_settings()?;
+    if let Some(name) = selected_profile(profile) {
+        settings = settings.with_profile(&name)?;
+    }
 
     let project_path_str = create_options
         .project_path
@@ -108,90 +416,214 @@ fn create(create_options: &CreateOptions) -> Result<()> {
                 .interact()
         })?;
 
-    let dest_path = PathBuf::from(&project_path_str);
-    if dest_path.exists() && !create_options.force {
-        let should_overwrite = cliclack::confirm(format!(
+    let dest_path = match &settings.projects_dir {
+        // A bare name goes in the projects directory
+        Some(projects_dir) if is_bare_name(&project_path_str) => {
+            resolve_settings_path(hayaku.hayaku_dir(), projects_dir).join(&project_path_str)
+        }
+        _ => PathBuf::from(&project_path_str),
+    };
+    // Project settings are found from the destination, which is only known now
+    let hayaku = hayaku.for_project(&dest_path)?;
+    let template_message = if hayaku.no_local_templates() {
+        "No local templates; using built-in templates only".to_string()
+    } else {
+        format!(
+            "Loaded templates from {}",
+            hayaku
+                .template_roots()
+                .iter()
+                .filter(|root| !root.templates.is_empty())
+                .map(|root| root.path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    };
+
+    cliclack::log::info(template_message.dimmed())?;
+    warn_about_duplicates(&hayaku)?;
+    let settings = apply_profile(
+        &hayaku,
+        hayaku.parse_settings_for(&dest_path)?,
+        profile,
+        &dest_path,
+    )?;
+    // An existing empty directory, such as `.` in a new folder, is used as is.
+    // Anything else is only removed once the project name has been checked.
+    let overwrite = dest_path.exists() && !is_empty_dir(&dest_path) && !create_options.force;
+    if overwrite {
+        let canonical = resolved_path(&dest_path);
+        if std::env::current_dir()?
+            .canonicalize()?
+            .starts_with(&canonical)
+        {
+            bail!(
+                "Refusing to clear {}, which contains the current directory\n\
+                 Use --force to write the project over its existing files instead",
+                canonical.display()
+            );
+        }
+        if !cliclack::confirm(format!(
             "Directory {} already exists. Overwrite?",
-            dest_path.display()
+            canonical.display()
         ))
-        .interact()?;
-        if should_overwrite {
-            std::fs::remove_dir_all(&dest_path)?;
-        } else {
+        .interact()?
+        {
             return Err(anyhow::anyhow!("Aborted by user"));
         }
     }
 
-    let template_path: PathBuf = if let Some(template_dir) = &create_options.template_dir {
-        if let Some(cli_defined) = template_dir {
-            validate_directory(cli_defined)?;
-            cli_defined.clone()
+    let (template_path, template_id): (PathBuf, Option<String>) =
+        if let Some(template_dir) = &create_options.template_dir {
+            if let Some(cli_defined) = template_dir {
+                validate_directory(cli_defined)?;
+                (cli_defined.clone(), None)
+            } else {
+                // Ask for the directory
+                let dir: String = cliclack::input("Template directory")
+                    .validate(|val: &String| validate_directory(Path::new(val)))
+                    .interact()?;
+                (PathBuf::from(dir), None)
+            }
+        } else if let Some(Some(template)) = &create_options.template {
+            let template_entry = hayaku
+                .get(template)
+                .ok_or_else(|| anyhow!("Template '{}' not found", template))?;
+            let path = hayaku.template_files(template_entry)?.to_path_buf();
+            (path, Some(template.clone()))
         } else {
-            // Ask for the directory
-            let dir: String = cliclack::input("Template directory")
-                .validate(|val: &String| validate_directory(&PathBuf::from(val)))
-                .interact()?;
-            PathBuf::from(dir)
-        }
-    } else {
-        let template_items: Vec<(String, String, String)> = hayaku
-            .all_templates()
-            .into_iter()
-            .map(|t| {
-                let id = t.config.name.clone();
-                let display_name = t
-                    .config
-                    .display_name
-                    .clone()
-                    .unwrap_or_else(|| t.config.name.clone());
-                let label = match t.origin {
-                    TemplateOrigin::BuiltIn => format!("{display_name} [built-in]"),
-                    TemplateOrigin::Local => display_name,
-                };
-                let description = t
-                    .config
-                    .description
-                    .clone()
-                    .unwrap_or_else(|| "".to_string());
-                (id, label, description)
-            })
-            .collect();
+            let template_items: Vec<(String, String, String)> = hayaku
+                .selectable_templates()
+                .into_iter()
+                .filter(|(_, t)| has_tags(t, &create_options.tag))
+                .map(|(id, t)| {
+                    let mut display_name = t
+                        .config
+                        .display_name
+                        .clone()
+                        .unwrap_or_else(|| t.config.name.clone());
+                    if !t.category.is_empty() {
+                        display_name = format!("{}/{display_name}", t.category.join("/"));
+                    }
+                    let label = match t.origin {
+                        TemplateOrigin::BuiltIn => format!("{display_name} [built-in]"),
+                        TemplateOrigin::Local if id.contains('/') => {
+                            format!("{display_name} [{}]", t.namespace)
+                        }
+                        TemplateOrigin::Local => display_name,
+                    };
+                    // Tags are part of the label so that typing `#web` filters by tag
+                    let label = t
+                        .config
+                        .tags
+                        .iter()
+                        .fold(label, |label, tag| format!("{label} #{tag}"));
+                    let description = t
+                        .config
+                        .description
+                        .clone()
+                        .unwrap_or_else(|| "".to_string());
+                    (id, label, description)
+                })
+                .collect();
 
-        let selection: String = cliclack::select(format!(
-            "Choose a template: {}",
-            "(Type to search)".dimmed()
-        ))
-        .items(&template_items)
-        .filter_mode()
-        .interact()?;
+            if template_items.is_empty() {
+                bail!(
+                    "No templates have the tag(s): {}",
+                    create_options.tag.join(", ")
+                );
+            }
 
-        let selected_template = hayaku
-            .get(&selection)
-            .ok_or_else(|| anyhow!("Selected template '{}' not found", selection))?;
-        selected_template.path.clone()
-    };
+            let mut select = cliclack::select(format!(
+                "Choose a template: {}",
+                "(Type to search)".dimmed()
+            ))
+            .items(&template_items)
+            .filter_mode();
+            let default_item = settings
+                .default_template
+                .as_deref()
+                .and_then(|id| hayaku.get(id))
+                .and_then(|default| {
+                    template_items
+                        .iter()
+                        .find(|(id, _, _)| hayaku.get(id).is_some_and(|t| t.path == default.path))
+                });
+            if let Some((id, _, _)) = default_item {
+                select = select.initial_value(id.clone());
+            }
+            let selection: String = select.interact()?;
+
+            let selected_template = hayaku
+                .get(&selection)
+                .ok_or_else(|| anyhow!("Selected template '{}' not found", selection))?;
+            (
+                hayaku.template_files(selected_template)?.to_path_buf(),
+                Some(selection),
+            )
+        };
 
     let template_config = TemplateConfig::try_from_dir(&template_path)?;
+    template_config.check_hayaku_version()?;
 
-    let project_name = env::project_name_from_path(&dest_path)?;
-    let context = env::build_context(&project_name, &template_config, &hayaku)?;
+    let project_name = match &create_options.name {
+        Some(name) => name.clone(),
+        None => env::project_name_from_path(&dest_path)?,
+    };
+    template_config
+        .check_project_name(&project_name)
+        .map_err(|err| match create_options.name {
+            Some(_) => err,
+            None => anyhow!("{err}\nUse --name to choose a different project name"),
+        })?;
+    if overwrite {
+        clear_directory(&dest_path)?;
+    }
+    let context = env::build_context(
+        &project_name,
+        &template_config,
+        &settings,
+        &create_options.answers.to_answers()?,
+        &env::HostInfo::detect(&dest_path, &template_path),
+    )?;
 
+    templating::log_context_variables(&context, &template_config)?;
     templating::create_project(&template_path, &dest_path, &context)?;
+    if template_config.answers_file && !create_options.no_answers_file {
+        AnswersFile::new(
+            template_id.as_deref().unwrap_or(&template_config.name),
+            &template_path,
+            &template_config,
+            &project_name,
+            &context,
+        )?
+        .write_to_dir(&dest_path)?;
+    }
     cliclack::log::success(format!(
         "{} Your project {} is ready.",
         "Success!".green(),
-        project_path_str.bold()
+        resolved_path(&dest_path).display().bold()
     ))?;
     Ok(())
 }
 
 pub fn run() -> Result<()> {
     let cli = Cli::try_parse()?;
+    // These only touch the user's settings file, so they still work when the
+    // settings are broken
+    if let Commands::Config(ref options) = cli.command
+        && !matches!(
+            options.command,
+            ConfigCommand::List { .. } | ConfigCommand::Get { .. }
+        )
+    {
+        return edit_settings_file(&Hayaku::user_settings_path()?, &options.command);
+    }
     let hayaku = Hayaku::try_new()?;
     match cli.command {
         Commands::Init => {}
         _ => {
-            if !hayaku.hayaku_dir().exists() {
+            if !hayaku.local_template_dir().exists() {
                 cliclack::log::warning(
                     "Consider running hayaku init to set up your local hayaku templates directory"
                         .yellow(),
@@ -201,75 +633,1057 @@ pub fn run() -> Result<()> {
     }
 
     match cli.command {
-        Commands::Create(ref create_options) => create(create_options),
-        Commands::Init => init(),
-        Commands::List => {
-            let visible_built_ins: Vec<_> = hayaku
-                .built_in_templates()
-                .values()
-                .filter(|template| !hayaku.templates().contains_key(&template.config.name))
+        Commands::Create(ref create_options) => {
+            create(&hayaku, create_options, cli.profile.as_deref())
+        }
+        Commands::Extract(ref extract_options) => extract(&hayaku, extract_options),
+        Commands::Init => init(hayaku),
+        Commands::Config(ref options) => config(&hayaku, options),
+        Commands::List(ref options) => list(&hayaku, options),
+        Commands::Info(ref options) => info(&hayaku, options),
+        Commands::Update(ref options) => update(&hayaku, options, cli.profile.as_deref()),
+        Commands::Add(ref options) => add(&hayaku, options, cli.profile.as_deref()),
+        Commands::Edit(ref options) => edit(&hayaku, options),
+        Commands::NewTemplate(ref options) => new_template(&hayaku, options),
+        Commands::Validate(ref options) => validate(&hayaku, options),
+        Commands::Test(ref options) => test(&hayaku, options),
+    }
+}
+
+fn warn_about_duplicates(hayaku: &Hayaku) -> Result<()> {
+    for duplicate in hayaku.duplicates() {
+        cliclack::log::warning(format!(
+            "Several templates are named {}; {} refers to the first one\n{}\n{}",
+            duplicate.name.bold(),
+            duplicate.name.bold(),
+            duplicate
+                .templates
+                .iter()
+                .map(|(id, path)| format!("· {} {}", id.bold(), path.display()))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            "Use one of these ids to choose one, or rename the duplicates".dimmed()
+        ))?;
+    }
+    Ok(())
+}
+
+/// Renders templates as a tree of their categories, sorted by name.
+fn template_tree(
+    templates: &[&TemplateEntry],
+    label: impl Fn(&TemplateEntry) -> String,
+) -> Vec<String> {
+    let mut templates = templates.to_vec();
+    templates.sort_by(|a, b| a.config.name.cmp(&b.config.name));
+    let entries: Vec<(Vec<String>, String)> = templates
+        .iter()
+        .map(|template| (template.category.clone(), label(template)))
+        .collect();
+    tree(&entries)
+}
+
+fn format_tags(tags: &[String]) -> String {
+    tags.iter()
+        .map(|tag| format!(" #{tag}").dimmed().to_string())
+        .collect()
+}
+
+fn has_tags(template: &TemplateEntry, tags: &[String]) -> bool {
+    tags.iter().all(|tag| template.config.has_tag(tag))
+}
+
+fn list(hayaku: &Hayaku, options: &ListOptions) -> Result<()> {
+    match options.format {
+        ListFormat::Text => {}
+        ListFormat::Json => {
+            println!("{}", TemplateListing::new(hayaku, &options.tag).to_json()?);
+            return Ok(());
+        }
+        ListFormat::Toml => {
+            print!("{}", TemplateListing::new(hayaku, &options.tag).to_toml()?);
+            return Ok(());
+        }
+    }
+    warn_about_duplicates(hayaku)?;
+    let visible_built_ins: Vec<_> = hayaku
+        .built_in_templates()
+        .values()
+        .filter(|template| !hayaku.templates().contains_key(&template.config.name))
+        .filter(|template| has_tags(template, &options.tag))
+        .collect();
+
+    if visible_built_ins.is_empty() {
+        cliclack::log::info("No built-in templates found.")?;
+    } else {
+        cliclack::log::info(format!(
+            "Built-in {}\n{}",
+            "(Templates that ship with hayaku)".dimmed(),
+            template_tree(&visible_built_ins, |t| format!(
+                "{} {}{}",
+                t.config.name.bold(),
+                t.config
+                    .description
+                    .clone()
+                    .unwrap_or_else(|| "".to_string()),
+                format_tags(&t.config.tags)
+            ))
+            .join("\n")
+        ))?;
+    }
+    if hayaku.templates().is_empty() {
+        cliclack::log::info(format!(
+            "No local templates found in {}",
+            hayaku
+                .template_roots()
+                .iter()
+                .map(|root| root.path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))?;
+        return Ok(());
+    }
+    for root in hayaku.template_roots() {
+        if root.templates.is_empty() {
+            continue;
+        }
+        let templates: Vec<_> = root
+            .templates
+            .values()
+            .filter(|template| has_tags(template, &options.tag))
+            .collect();
+        if templates.is_empty() {
+            continue;
+        }
+        cliclack::log::info(format!(
+            "{} {}\n{}",
+            root.name,
+            root.path.display().dimmed(),
+            template_tree(&templates, |t| {
+                let shadowed = hayaku
+                    .templates()
+                    .get(&t.config.name)
+                    .is_some_and(|winner| winner.path != t.path);
+                format!(
+                    "{} {}{}{}",
+                    t.config.name.bold(),
+                    t.config
+                        .description
+                        .clone()
+                        .unwrap_or_else(|| "".to_string()),
+                    format_tags(&t.config.tags),
+                    if shadowed {
+                        " (shadowed)".dimmed().to_string()
+                    } else {
+                        "".to_string()
+                    }
+                )
+            })
+            .join("\n")
+        ))?;
+    }
+    Ok(())
+}
+
+fn add(hayaku: &Hayaku, options: &AddOptions, profile: Option<&str>) -> Result<()> {
+    validate_directory(&options.into)?;
+    let hayaku = &hayaku.for_project(&options.into)?;
+    let template = hayaku
+        .get(&options.template)
+        .ok_or_else(|| anyhow!("Template '{}' not found", options.template))?;
+    let config = &template.config;
+    config.check_hayaku_version()?;
+    let template_path = hayaku.template_files(template)?;
+
+    let name = match &options.name {
+        Some(name) => name.clone(),
+        None => cliclack::input("Name")
+            .placeholder("my_component")
+            .validate(|val: &String| {
+                if val.is_empty() {
+                    Err("Value is required")
+                } else {
+                    Ok(())
+                }
+            })
+            .interact()?,
+    };
+    config.check_project_name(&name)?;
+    let context = env::build_context(
+        &name,
+        config,
+        &apply_profile(
+            hayaku,
+            hayaku.parse_settings_for(&options.into)?,
+            profile,
+            &options.into,
+        )?,
+        &options.answers.to_answers()?,
+        &env::HostInfo::detect(&options.into, template_path),
+    )?;
+    let rendered = templating::render_project(template_path, &context)?;
+
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Resolution {
+        Overwrite,
+        Skip,
+        OverwriteAll,
+        SkipAll,
+    }
+    let mut remembered = None;
+    let mut written = Vec::new();
+    let mut skipped = Vec::new();
+    for (rel_path, status) in add::plan(&options.into, &rendered)? {
+        let resolution = match status {
+            FileStatus::Unchanged => continue,
+            FileStatus::New => Resolution::Overwrite,
+            FileStatus::Conflict => match remembered {
+                Some(resolution) => resolution,
+                None => {
+                    let resolution =
+                        cliclack::select(format!("{} already exists", rel_path.display().bold()))
+                            .item(Resolution::Skip, "Keep the existing file", "")
+                            .item(Resolution::Overwrite, "Overwrite it", "")
+                            .item(Resolution::SkipAll, "Keep all existing files", "")
+                            .item(Resolution::OverwriteAll, "Overwrite all existing files", "")
+                            .interact()?;
+                    if matches!(resolution, Resolution::SkipAll | Resolution::OverwriteAll) {
+                        remembered = Some(resolution);
+                    }
+                    resolution
+                }
+            },
+        };
+        if matches!(resolution, Resolution::Overwrite | Resolution::OverwriteAll) {
+            templating::write_file(&options.into.join(&rel_path), &rendered[&rel_path])?;
+            written.push(rel_path);
+        } else {
+            skipped.push(rel_path);
+        }
+    }
+
+    let list = |paths: &[PathBuf]| {
+        paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    if !skipped.is_empty() {
+        cliclack::log::warning(format!("Kept existing files\n{}", list(&skipped)))?;
+    }
+    if written.is_empty() {
+        cliclack::log::info("No files were written")?;
+    } else {
+        cliclack::log::success(format!(
+            "{} Added {} to {}\n{}",
+            "Success!".green(),
+            template.config.name.bold(),
+            options.into.display(),
+            list(&written)
+        ))?;
+    }
+    Ok(())
+}
+
+fn update(hayaku: &Hayaku, options: &UpdateOptions, profile: Option<&str>) -> Result<()> {
+    let project_dir = &options.project_dir;
+    let hayaku = &hayaku.for_project(project_dir)?;
+    let recorded = AnswersFile::try_from_dir(project_dir)?;
+    let template_path = match hayaku.get(&recorded.template.id) {
+        Some(template) => hayaku.template_files(template)?.to_path_buf(),
+        None => recorded.template.path.clone(),
+    };
+    validate_directory(&template_path)?;
+    let config = TemplateConfig::try_from_dir(&template_path)?;
+    config.check_hayaku_version()?;
+
+    // The template as it was when the project was created
+    let checkout = tempfile::tempdir()?;
+    let base_path = match &options.base_template {
+        Some(base_template) => base_template.clone(),
+        None => {
+            let commit = recorded.template.commit.as_deref().ok_or_else(|| {
+                anyhow!(
+                    "No template commit is recorded in {}, so the version the project was created from can't be recovered.\nPass it with --base-template <DIR>",
+                    answers_file::ANSWERS_FILE
+                )
+            })?;
+            if recorded.template.dirty {
+                return Err(anyhow!(
+                    "The template had uncommitted changes when the project was created, so commit {commit} doesn't match the version the project was created from.\nPass it with --base-template <DIR>"
+                ));
+            }
+            let repo = git::toplevel(&template_path).ok_or_else(|| {
+                anyhow!(
+                    "{} is not in a git repository; pass the original template with --base-template <DIR>",
+                    template_path.display()
+                )
+            })?;
+            let rel_path = template_path
+                .canonicalize()?
+                .strip_prefix(&repo)?
+                .to_path_buf();
+            git::checkout_commit(&repo, commit, checkout.path())?;
+            checkout.path().join(rel_path)
+        }
+    };
+    let base_config = TemplateConfig::try_from_dir(&base_path)?;
+
+    let settings = apply_profile(
+        hayaku,
+        hayaku.parse_settings_for(project_dir)?,
+        profile,
+        project_dir,
+    )?;
+    let host = env::HostInfo::detect(project_dir, &template_path);
+    let context = env::build_context(
+        &recorded.project_name,
+        &config,
+        &settings,
+        &env::Answers::prompt_missing(recorded.answers.clone()),
+        &host,
+    )?;
+    let mut answers = recorded.answers.clone();
+    answers.extend(answers_file::collect_answers(&config, &context, true)?);
+    let mut base_host = host.clone();
+    if let Some(recorded_host) = &recorded.host {
+        recorded_host.apply_to(&mut base_host);
+    }
+    let base_context = env::build_context(
+        &recorded.project_name,
+        &base_config,
+        &settings,
+        &env::Answers::fixed(answers).with_placeholders(),
+        &base_host,
+    )?;
+
+    let updates = update::update_project(
+        project_dir,
+        &templating::render_project(&base_path, &base_context)?,
+        &templating::render_project(&template_path, &context)?,
+    )?;
+    AnswersFile::new(
+        &recorded.template.id,
+        &template_path,
+        &config,
+        &recorded.project_name,
+        &context,
+    )?
+    .write_to_dir(project_dir)?;
+
+    if updates.is_empty() {
+        cliclack::log::success("Already up to date")?;
+        return Ok(());
+    }
+    let mut conflicts = 0;
+    let lines: Vec<String> = updates
+        .iter()
+        .map(|file_update| match file_update {
+            FileUpdate::Merged(path) => format!("{} {}", "M".green(), path.display()),
+            FileUpdate::Added(path) => format!("{} {}", "A".green(), path.display()),
+            FileUpdate::Removed(path) => format!("{} {}", "D".green(), path.display()),
+            FileUpdate::Conflicted(path, count) => {
+                conflicts += 1;
+                format!(
+                    "{} {} {}",
+                    "C".red(),
+                    path.display(),
+                    format!("({count} conflicting regions)").dimmed()
+                )
+            }
+            FileUpdate::Rejected(path) => {
+                conflicts += 1;
+                format!(
+                    "{} {} {}",
+                    "R".red(),
+                    path.display(),
+                    "(deleted in the project; template version saved as .rej)".dimmed()
+                )
+            }
+            FileUpdate::Kept(path) => format!(
+                "{} {} {}",
+                "K".yellow(),
+                path.display(),
+                "(removed from the template but changed in the project)".dimmed()
+            ),
+        })
+        .collect();
+    cliclack::log::info(format!("Updated files\n{}", lines.join("\n")))?;
+    if conflicts > 0 {
+        cliclack::log::warning(format!(
+            "{conflicts} file(s) need attention. Resolve the conflict markers and .rej files, then review the changes."
+        ))?;
+    } else {
+        cliclack::log::success(format!("{} Project updated", "Success!".green()))?;
+    }
+    Ok(())
+}
+
+fn info(hayaku: &Hayaku, options: &InfoOptions) -> Result<()> {
+    let template = hayaku
+        .get(&options.template)
+        .ok_or_else(|| anyhow!("Template '{}' not found", options.template))?;
+    let config = &template.config;
+    let template_path = hayaku.template_files(template)?;
+
+    let mut details = vec![format!(
+        "{} {}",
+        config.display_name.as_ref().unwrap_or(&config.name).bold(),
+        template.qualified_id().dimmed()
+    )];
+    if let Some(description) = &config.description {
+        details.push(description.clone());
+    }
+    details.push(String::new());
+    let mut field = |label: &str, value: String| {
+        details.push(format!("{} {value}", format!("{label}:").dimmed()));
+    };
+    if let Some(version) = &config.version {
+        field("Version", version.to_string());
+    }
+    if let Some(author) = &config.author {
+        field("Author", author.clone());
+    }
+    if let Some(homepage) = &config.homepage {
+        field("Homepage", homepage.clone());
+    }
+    if !config.tags.is_empty() {
+        field("Tags", config.tags.join(", "));
+    }
+    if let Some(min_version) = &config.min_hayaku_version {
+        field("Requires", format!("hayaku {min_version} or newer"));
+    }
+    field(
+        "Source",
+        match template.origin {
+            TemplateOrigin::Local => "local".to_string(),
+            TemplateOrigin::BuiltIn => "built-in".to_string(),
+        },
+    );
+    field("Path", template.path.display().to_string());
+    if template.origin == TemplateOrigin::Local
+        && hayaku.built_in_templates().contains_key(&config.name)
+    {
+        field("Overrides", format!("built-in/{}", config.name));
+    }
+    cliclack::log::info(details.join("\n"))?;
+
+    if config.env.is_empty() {
+        cliclack::log::info("Variables: none")?;
+    } else {
+        let variables: Vec<String> = config
+            .env
+            .iter()
+            .map(|(key, env_cfg)| {
+                let (kind, prompt, extra) = match env_cfg {
+                    EnvVarConfig::String {
+                        prompt,
+                        default,
+                        secret,
+                    } => (
+                        if *secret { "secret string" } else { "string" },
+                        prompt,
+                        default
+                            .as_ref()
+                            .map(|default| format!("default: {default}")),
+                    ),
+                    EnvVarConfig::Choices {
+                        prompt,
+                        choices,
+                        default,
+                    } => (
+                        "choices",
+                        prompt,
+                        Some(match default {
+                            Some(default) => {
+                                format!("{}, default: {default}", choices.join(" | "))
+                            }
+                            None => choices.join(" | "),
+                        }),
+                    ),
+                    EnvVarConfig::Bool { prompt, default } => {
+                        ("bool", prompt, Some(format!("default: {default}")))
+                    }
+                };
+                format!(
+                    "{} {} {prompt}{}",
+                    env::canonical_env_key(key).bold(),
+                    format!("({kind})").dimmed(),
+                    extra
+                        .map(|extra| format!(" {}", format!("[{extra}]").dimmed()))
+                        .unwrap_or_default()
+                )
+            })
+            .collect();
+        cliclack::log::info(format!("Variables\n{}", variables.join("\n")))?;
+    }
+
+    cliclack::log::info(format!(
+        "Files\n{}",
+        path_tree(&templating::output_paths(template_path)?).join("\n")
+    ))?;
+
+    for readme in ["README.md", "README.md.tera", "README"] {
+        let path = template_path.join(readme);
+        if path.is_file() {
+            cliclack::log::info(format!(
+                "{}\n{}",
+                readme,
+                std::fs::read_to_string(&path)?.trim_end()
+            ))?;
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Renders relative file paths as a tree.
+fn path_tree(paths: &[PathBuf]) -> Vec<String> {
+    let entries: Vec<(Vec<String>, String)> = paths
+        .iter()
+        .map(|path| {
+            let mut components: Vec<String> = path
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
                 .collect();
+            let file = components.pop().unwrap_or_default();
+            (components, file)
+        })
+        .collect();
+    tree(&entries)
+}
 
-            if visible_built_ins.is_empty() {
-                cliclack::log::info("No built-in templates found.")?;
-            } else {
-                cliclack::log::info(format!(
-                    "Built-in {}\n{}",
-                    "(Templates that ship with hayaku)".dimmed(),
-                    visible_built_ins
-                        .iter()
-                        .map(|t| format!(
-                            "· {} {}",
-                            t.config.name.bold(),
-                            t.config
-                                .description
-                                .clone()
-                                .unwrap_or_else(|| "".to_string())
-                        ))
-                        .collect::<Vec<_>>()
-                        .join("\n")
-                ))?;
+/// Renders labels as a tree of the directories they are in, with directories
+/// listed before the labels at each level.
+fn tree(entries: &[(Vec<String>, String)]) -> Vec<String> {
+    fn render(entries: &[(&[String], &str)], prefix: &str, lines: &mut Vec<String>) {
+        let mut dirs: Vec<&str> = entries
+            .iter()
+            .filter_map(|(dirs, _)| dirs.first().map(String::as_str))
+            .collect();
+        dirs.sort();
+        dirs.dedup();
+        let leaves: Vec<&str> = entries
+            .iter()
+            .filter(|(dirs, _)| dirs.is_empty())
+            .map(|(_, label)| *label)
+            .collect();
+
+        let count = dirs.len() + leaves.len();
+        for (i, dir) in dirs.iter().enumerate() {
+            let last = i + 1 == count;
+            lines.push(format!(
+                "{prefix}{} {}",
+                if last { "└─" } else { "├─" },
+                format!("{dir}/").dimmed()
+            ));
+            let children: Vec<(&[String], &str)> = entries
+                .iter()
+                .filter(|(dirs, _)| dirs.first().is_some_and(|first| first == dir))
+                .map(|(dirs, label)| (&dirs[1..], *label))
+                .collect();
+            let child_prefix = format!("{prefix}{}", if last { "   " } else { "│  " });
+            render(&children, &child_prefix, lines);
+        }
+        for (i, label) in leaves.iter().enumerate() {
+            let last = dirs.len() + i + 1 == count;
+            lines.push(format!(
+                "{prefix}{} {label}",
+                if last { "└─" } else { "├─" }
+            ));
+        }
+    }
+
+    let entries: Vec<(&[String], &str)> = entries
+        .iter()
+        .map(|(dirs, label)| (dirs.as_slice(), label.as_str()))
+        .collect();
+    let mut lines = Vec::new();
+    render(&entries, "", &mut lines);
+    lines
+}
+
+fn extract(hayaku: &Hayaku, options: &ExtractOptions) -> Result<()> {
+    validate_directory(&options.project_dir)?;
+    let project_name = match &options.project_name {
+        Some(name) => name.clone(),
+        None => env::project_name_from_path(&options.project_dir.canonicalize()?)?,
+    };
+    let template_dir = hayaku.local_template_dir().join(&options.name);
+
+    let report = extract::extract(
+        &options.project_dir,
+        &template_dir,
+        &options.name,
+        &project_name,
+    )?;
+
+    if report.substitutions.is_empty() {
+        cliclack::log::warning(format!(
+            "No occurrences of {} were found",
+            project_name.bold()
+        ))?;
+    } else {
+        cliclack::log::info(format!(
+            "Substitutions\n{}",
+            report
+                .substitutions
+                .iter()
+                .map(|s| {
+                    let location = match s.location {
+                        SubstitutionLocation::Path => "path",
+                        SubstitutionLocation::Contents => "contents",
+                    };
+                    format!(
+                        "· {} {} {} → {} {}",
+                        s.file.display().bold(),
+                        format!("({location})").dimmed(),
+                        s.from,
+                        s.to,
+                        format!("×{}", s.count).dimmed()
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        ))?;
+    }
+    if !report.tera_files.is_empty() {
+        cliclack::log::info(format!(
+            "Renamed to .tera\n{}",
+            report
+                .tera_files
+                .iter()
+                .map(|path| format!("· {}", path.display()))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ))?;
+    }
+    if !report.skipped_files.is_empty() {
+        cliclack::log::warning(format!(
+            "Skipped files that aren't UTF-8 text, such as images, since templates can only contain text\n{}",
+            report
+                .skipped_files
+                .iter()
+                .map(|path| format!("· {}", path.display()))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ))?;
+    }
+    cliclack::log::success(format!(
+        "{} Extracted {} files into {}",
+        "Success!".green(),
+        report.files,
+        template_dir.display().bold()
+    ))?;
+    Ok(())
+}
+
+fn open_in_editor(hayaku: &Hayaku, path: &Path) -> Result<()> {
+    run_editor(&hayaku.parse_settings()?.editor_command()?, path)
+}
+
+fn run_editor(command: &[String], path: &Path) -> Result<()> {
+    let status = std::process::Command::new(&command[0])
+        .args(&command[1..])
+        .arg(path)
+        .status()
+        .map_err(|e| anyhow!("Failed to open editor `{}`: {}", command.join(" "), e))?;
+    if !status.success() {
+        bail!("Editor `{}` exited with {}", command.join(" "), status);
+    }
+    Ok(())
+}
+
+fn edit(hayaku: &Hayaku, options: &EditOptions) -> Result<()> {
+    let Some(name) = &options.template else {
+        return open_in_editor(hayaku, hayaku.hayaku_dir());
+    };
+    let path = match hayaku.get(name) {
+        Some(template) if template.origin == TemplateOrigin::Local => template.path.clone(),
+        Some(template) => {
+            if !cliclack::confirm(format!(
+                "{} is a built-in template. Copy it to your local templates so you can override it?",
+                name.bold()
+            ))
+            .interact()?
+            {
+                bail!("Built-in templates can't be edited in place");
+            }
+            let path = hayaku.copy_to_local(template)?;
+            cliclack::log::success(format!("Copied {} to {}", name.bold(), path.display()))?;
+            path
+        }
+        None => {
+            if name.contains('/')
+                || !cliclack::confirm(format!(
+                    "Template {} doesn't exist. Create it?",
+                    name.bold()
+                ))
+                .interact()?
+            {
+                bail!("Template '{name}' not found");
+            }
+            return new_template(
+                hayaku,
+                &NewTemplateOptions {
+                    name: Some(name.clone()),
+                },
+            );
+        }
+    };
+    open_in_editor(hayaku, &path)
+}
+
+/// Formats a setting for printing. Strings are printed without quotes so
+/// that the output can be used in scripts.
+fn format_setting(value: &toml::Value) -> Result<String> {
+    Ok(match value {
+        toml::Value::String(text) => text.clone(),
+        toml::Value::Table(table) => toml::to_string_pretty(table)?.trim_end().to_string(),
+        value => value.to_string(),
+    })
+}
+
+fn config(hayaku: &Hayaku, options: &ConfigOptions) -> Result<()> {
+    match &options.command {
+        ConfigCommand::List { show_origin } => {
+            let layers = hayaku.settings_layers(Path::new("."))?;
+            for (key, value) in layers.settings()?.entries()? {
+                match layers.origin(&key).filter(|_| *show_origin) {
+                    Some(layer) => println!("{}\t{key} = {value}", layer.path.display()),
+                    None => println!("{key} = {value}"),
+                }
+            }
+        }
+        ConfigCommand::Get { key } => match hayaku.parse_settings()?.get(key)? {
+            Some(value) => println!("{}", format_setting(&value)?),
+            None => bail!("{key} is not set"),
+        },
+        _ => edit_settings_file(hayaku.settings_config_path(), &options.command)?,
+    }
+    Ok(())
+}
+
+/// Runs the `config` commands that change or show the user's settings file.
+/// The file is edited as TOML, without loading the settings.
+fn edit_settings_file(path: &Path, command: &ConfigCommand) -> Result<()> {
+    match command {
+        ConfigCommand::Set { key, value } => {
+            let parsed = settings::parse_setting_value(value);
+            // A string setting given something that looks like another type,
+            // e.g. `default_template true`, takes the text as is
+            if let Err(err) = settings::set_in_file(path, key, parsed.clone())
+                && (parsed.is_str()
+                    || settings::set_in_file(path, key, toml::Value::String(value.clone()))
+                        .is_err())
+            {
+                return Err(err);
+            }
+            cliclack::log::success(format!("Set {}", key.bold()))?;
+        }
+        ConfigCommand::Unset { key } => {
+            if !settings::unset_in_file(path, key)? {
+                bail!("{key} is not set");
             }
-            if hayaku.templates().is_empty() {
-                cliclack::log::info(format!(
-                    "No local templates found in {}",
-                    hayaku.local_template_dir().display()
-                ))?;
+            cliclack::log::success(format!("Unset {}", key.bold()))?;
+        }
+        ConfigCommand::Edit => {
+            if !path.exists() {
+                HayakuSettings::default().write_to_file(path)?;
+            }
+            let settings = HayakuSettings {
+                editor: settings::editor_in_file(path)?,
+                ..Default::default()
+            };
+            run_editor(&settings.editor_command()?, path)?;
+        }
+        ConfigCommand::Path => println!("{}", path.display()),
+        ConfigCommand::List { .. } | ConfigCommand::Get { .. } => {
+            unreachable!("list and get read the merged settings")
+        }
+    }
+    Ok(())
+}
+
+fn optional_input(prompt: &str) -> Result<Option<String>> {
+    let value: String = cliclack::input(prompt).required(false).interact()?;
+    Ok(if value.is_empty() { None } else { Some(value) })
+}
+
+/// Asks for a new variable. Its key must differ from those in `existing`
+/// once both are upper-cased, since they would otherwise share a context name.
+fn prompt_env_var(
+    existing: &indexmap::IndexMap<String, EnvVarConfig>,
+) -> Result<(String, EnvVarConfig)> {
+    let existing: Vec<String> = existing.keys().map(|k| env::canonical_env_key(k)).collect();
+    let key: String = cliclack::input("Variable name")
+        .placeholder("crate_type")
+        .validate(move |val: &String| {
+            let key = env::canonical_env_key(val);
+            if val.trim().is_empty() {
+                Err("Value is required".to_string())
+            } else if existing.contains(&key) {
+                Err(format!("{key} is already a variable"))
             } else {
-                cliclack::log::info(format!(
-                    "Local templates {}\n{}",
-                    hayaku.local_template_dir().display().dimmed(),
-                    hayaku
-                        .templates()
-                        .values()
-                        .map(|t| format!(
-                            "· {} {}",
-                            t.config.name.bold(),
-                            t.config
-                                .description
-                                .clone()
-                                .unwrap_or_else(|| "".to_string())
-                        ))
-                        .collect::<Vec<_>>()
-                        .join("\n")
-                ))?;
+                Ok(())
+            }
+        })
+        .interact()?;
+    let kind = cliclack::select("Variable type")
+        .items(&[
+            ("string", "String", "Free-form text"),
+            ("choices", "Choices", "One of a list of values"),
+            ("bool", "Bool", "Yes or no"),
+        ])
+        .interact()?;
+    let prompt: String = cliclack::input("Prompt").interact()?;
+
+    let env_cfg = match kind {
+        "choices" => {
+            let split = |val: &str| -> Vec<String> {
+                val.split(',')
+                    .map(|c| c.trim().to_string())
+                    .filter(|c| !c.is_empty())
+                    .collect()
+            };
+            let choices = split(
+                &cliclack::input("Choices (comma separated)")
+                    .validate(move |val: &String| {
+                        if split(val).is_empty() {
+                            Err("Enter at least one choice")
+                        } else {
+                            Ok(())
+                        }
+                    })
+                    .interact::<String>()?,
+            );
+            let default_items: Vec<(String, String, String)> =
+                std::iter::once(("".to_string(), "(none)".to_string(), "".to_string()))
+                    .chain(
+                        choices
+                            .iter()
+                            .map(|c| (c.clone(), c.clone(), "".to_string())),
+                    )
+                    .collect();
+            let default: String = cliclack::select("Default choice")
+                .items(&default_items)
+                .interact()?;
+            EnvVarConfig::Choices {
+                prompt,
+                choices,
+                default: if default.is_empty() {
+                    None
+                } else {
+                    Some(default)
+                },
             }
-            Ok(())
         }
-        Commands::Edit => {
-            let local_templates = Hayaku::try_new()?;
-            std::process::Command::new("code")
-                .arg(local_templates.hayaku_dir())
-                .status()
-                .map_err(|e| anyhow::anyhow!("Failed to open code editor: {}", e))?;
+        "bool" => EnvVarConfig::Bool {
+            prompt,
+            default: cliclack::confirm("Default to yes?").interact()?,
+        },
+        _ => EnvVarConfig::String {
+            prompt,
+            default: optional_input("Default value (optional)")?,
+            secret: false,
+        },
+    };
+    Ok((key, env_cfg))
+}
 
+fn new_template(hayaku: &Hayaku, options: &NewTemplateOptions) -> Result<()> {
+    let local_template_dir = hayaku.local_template_dir().to_path_buf();
+    let mut name_input = cliclack::input("Template name").validate(move |val: &String| {
+        if val.is_empty() {
+            Err("Value is required".to_string())
+        } else if val.contains(['/', '\\']) {
+            Err("Template names cannot contain path separators".to_string())
+        } else if local_template_dir.join(val).exists() {
+            Err(format!(
+                "Template directory {} already exists",
+                local_template_dir.join(val).display()
+            ))
+        } else {
             Ok(())
         }
+    });
+    if let Some(name) = &options.name {
+        name_input = name_input.default_input(name);
+    }
+    let name: String = name_input.interact()?;
+
+    let template_dir = hayaku.local_template_dir().join(&name);
+
+    let display_name = optional_input("Display name (optional)")?;
+    let description = optional_input("Description (optional)")?;
+    let author = optional_input("Author (optional)")?;
+
+    let mut env = indexmap::IndexMap::new();
+    while cliclack::confirm(if env.is_empty() {
+        "Add a variable?"
+    } else {
+        "Add another variable?"
+    })
+    .interact()?
+    {
+        let (key, env_cfg) = prompt_env_var(&env)?;
+        env.insert(key, env_cfg);
+    }
+
+    let config = ConfigToml {
+        template: TemplateSection {
+            name,
+            display_name,
+            description,
+            author,
+            ..Default::default()
+        },
+        project_name: None,
+        env,
+    };
+    new_template::scaffold(&template_dir, &config)?;
+    cliclack::log::success(format!(
+        "{} Created template {}",
+        "Success!".green(),
+        template_dir.display().bold()
+    ))?;
+
+    if cliclack::confirm("Open the new template in your editor?").interact()? {
+        open_in_editor(hayaku, &template_dir)?;
+    }
+    Ok(())
+}
+
+fn validate(hayaku: &Hayaku, options: &ValidateOptions) -> Result<()> {
+    let template_dirs: Vec<PathBuf> = if let Some(template_dir) = &options.template_dir {
+        validate_directory(template_dir)?;
+        vec![template_dir.clone()]
+    } else if let Some(template) = &options.template {
+        let entry = hayaku
+            .get(template)
+            .ok_or_else(|| anyhow!("Template '{}' not found", template))?;
+        vec![hayaku.template_files(entry)?.to_path_buf()]
+    } else if options.all {
+        hayaku
+            .all_templates()
+            .into_iter()
+            .map(|t| Ok(hayaku.template_files(t)?.to_path_buf()))
+            .collect::<Result<_>>()?
+    } else {
+        bail!("Specify a template, --template-dir or --all");
+    };
+
+    let global_env: Vec<String> = hayaku
+        .parse_settings()?
+        .global_env
+        .map(|env| env.into_keys().collect())
+        .unwrap_or_default();
+
+    let mut problems = 0;
+    for template_dir in &template_dirs {
+        let diagnostics = validate::validate_template(template_dir, &global_env)?;
+        for diagnostic in &diagnostics {
+            eprintln!("{diagnostic}");
+        }
+        problems += diagnostics.len();
+    }
+
+    if problems > 0 {
+        bail!(
+            "Found {} problem{} in {} template{}",
+            problems,
+            if problems == 1 { "" } else { "s" },
+            template_dirs.len(),
+            if template_dirs.len() == 1 { "" } else { "s" }
+        );
+    }
+    cliclack::log::success(format!(
+        "{} template{} passed validation",
+        template_dirs.len(),
+        if template_dirs.len() == 1 { "" } else { "s" }
+    ))?;
+    Ok(())
+}
+
+fn test(hayaku: &Hayaku, options: &TestOptions) -> Result<()> {
+    let template_dir = if let Some(template_dir) = &options.template_dir {
+        validate_directory(template_dir)?;
+        template_dir.clone()
+    } else if let Some(template) = &options.template {
+        let entry = hayaku
+            .get(template)
+            .ok_or_else(|| anyhow!("Template '{}' not found", template))?;
+        hayaku.template_files(entry)?.to_path_buf()
+    } else {
+        bail!("Specify a template or --template-dir");
+    };
+
+    let config = TemplateConfig::try_from_dir(&template_dir)?;
+    config.check_hayaku_version()?;
+    let fixtures = template_test::discover_fixtures(&template_dir)?;
+    if fixtures.is_empty() {
+        bail!(
+            "No fixtures found in {}",
+            template_dir.join(template_test::FIXTURE_DIR).display()
+        );
+    }
+
+    let mut failed = 0;
+    for fixture in &fixtures {
+        let result = template_test::run_fixture(&template_dir, &config, fixture, options.update)?;
+        for diff in &result.diffs {
+            match diff {
+                FileDiff::Added(path) => println!("+ {} (not in snapshot)", path.display()),
+                FileDiff::Removed(path) => println!("- {} (no longer rendered)", path.display()),
+                FileDiff::Changed(_, diff) => print!("{diff}"),
+            }
+        }
+        if let Some(check_failure) = &result.check_failure {
+            println!("{check_failure}");
+        }
+
+        if result.passed() {
+            let status = if result.updated { "updated" } else { "ok" };
+            cliclack::log::success(format!("{} {}", result.name.bold(), status.green()))?;
+        } else {
+            failed += 1;
+            let reason = if result.missing_snapshot {
+                "no snapshot; run with --update to create it".to_string()
+            } else if result.check_failure.is_some() {
+                "check failed".to_string()
+            } else {
+                format!("{} file(s) differ from the snapshot", result.diffs.len())
+            };
+            cliclack::log::error(format!("{} {}", result.name.bold(), reason.red()))?;
+        }
+    }
+
+    if failed > 0 {
+        bail!("{} of {} fixtures failed", failed, fixtures.len());
     }
+    Ok(())
 }
 
-fn init() -> Result<()> {
-    let hayaku = Hayaku::try_new()?;
+fn init(mut hayaku: Hayaku) -> Result<()> {
+    if let Some(xdg) = hayaku.xdg_migration()?
+        && cliclack::confirm(format!(
+            "Move your settings and templates from {} to the XDG directories ({} and {})?",
+            hayaku.hayaku_dir().display(),
+            xdg.config.display(),
+            xdg.templates.display()
+        ))
+        .interact()?
+    {
+        hayaku.dirs().migrate_to(&xdg)?;
+        if hayaku.hayaku_dir().exists() {
+            cliclack::log::warning(format!(
+                "{} still has other files in it, so it was kept",
+                hayaku.hayaku_dir().display()
+            ))?;
+        }
+        hayaku = Hayaku::try_new()?;
+        cliclack::log::success("Moved to the XDG directories")?;
+    }
     if hayaku.local_template_dir().exists() {
         cliclack::log::success(format!(
             "Template directory found: {}",
@@ -310,6 +1724,7 @@ fn init() -> Result<()> {
                 "LICENSE".to_string(),
                 toml::Value::String(default_license.to_string()),
             )])),
+            ..Default::default()
         };
         settings.write_to_file(hayaku.settings_config_path())?;
     }
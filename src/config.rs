@@ -1,21 +1,62 @@
 use crate::env::EnvVarConfig;
 use anyhow::{Context, Result, anyhow};
+use indexmap::IndexMap;
+use regex::Regex;
+use semver::Version;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::path::Path;
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub struct TemplateSection {
     pub name: String,
     pub display_name: Option<String>,
     pub description: Option<String>,
     pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    pub version: Option<Version>,
+    /// The oldest hayaku release that can render this template
+    pub min_hayaku_version: Option<Version>,
+    pub homepage: Option<String>,
+    /// Set to `false` to not write `.hayaku-answers.toml` into new projects
+    pub answers_file: Option<bool>,
+}
+
+/// A `[project_name]` rule that project names must follow.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProjectNameRule {
+    /// A regular expression that the whole name must match
+    pub pattern: String,
+    /// Explains the rule when a name doesn't match
+    pub message: Option<String>,
+}
+
+impl ProjectNameRule {
+    pub fn regex(&self) -> Result<Regex> {
+        Regex::new(&format!("^(?:{})$", self.pattern))
+            .map_err(|err| anyhow!("Invalid project_name pattern `{}`:\n{err}", self.pattern))
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ConfigToml {
     pub template: TemplateSection,
-    #[serde(default)]
-    pub env: HashMap<String, EnvVarConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project_name: Option<ProjectNameRule>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub env: IndexMap<String, EnvVarConfig>,
+}
+
+impl ConfigToml {
+    pub fn write_to_dir(&self, dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join("hayaku.toml");
+        let toml_string = toml::to_string_pretty(self)
+            .map_err(|err| anyhow!("Failed to serialize template config to TOML:\n{err}"))?;
+        std::fs::write(&path, toml_string)
+            .with_context(|| format!("Failed to write config file {}", path.display()))?;
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -24,7 +65,13 @@ pub struct TemplateConfig {
     pub display_name: Option<String>,
     pub description: Option<String>,
     pub author: Option<String>,
-    pub env: HashMap<String, EnvVarConfig>,
+    pub tags: Vec<String>,
+    pub version: Option<Version>,
+    pub min_hayaku_version: Option<Version>,
+    pub homepage: Option<String>,
+    pub answers_file: bool,
+    pub project_name: Option<ProjectNameRule>,
+    pub env: IndexMap<String, EnvVarConfig>,
 }
 
 impl TemplateConfig {
@@ -34,8 +81,78 @@ impl TemplateConfig {
             display_name: None,
             description: None,
             author: None,
-            env: HashMap::new(),
+            tags: Vec::new(),
+            version: None,
+            min_hayaku_version: None,
+            homepage: None,
+            answers_file: true,
+            project_name: None,
+            env: IndexMap::new(),
+        }
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+
+    /// Fails when the template needs a newer version of hayaku than this one.
+    pub fn check_hayaku_version(&self) -> Result<()> {
+        let current = Version::parse(env!("CARGO_PKG_VERSION"))?;
+        match &self.min_hayaku_version {
+            Some(required) if current < *required => Err(anyhow!(
+                "Template '{}' requires hayaku {required} or newer, but this is hayaku {current}.\nUpgrade with: cargo install hayaku",
+                self.name
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails when `name` doesn't follow the template's `[project_name]` rule.
+    pub fn check_project_name(&self, name: &str) -> Result<()> {
+        let Some(rule) = &self.project_name else {
+            return Ok(());
+        };
+        if rule.regex()?.is_match(name) {
+            return Ok(());
+        }
+        Err(match &rule.message {
+            Some(message) => anyhow!(
+                "'{name}' is not a valid name for a {} project: {message}",
+                self.name
+            ),
+            None => anyhow!(
+                "'{name}' is not a valid name for a {} project; names must match `{}`",
+                self.name,
+                rule.pattern
+            ),
+        })
+    }
+
+    /// Parses the contents of the `hayaku.toml` at `config_path`.
+    pub fn parse(content: &str, config_path: &std::path::Path) -> Result<Self> {
+        let config: ConfigToml = toml::from_str(content).map_err(|err| {
+            anyhow!(
+                "Failed to parse config file {}:\n{err}",
+                config_path.display()
+            )
+        })?;
+        if let Some(rule) = &config.project_name {
+            rule.regex()
+                .with_context(|| format!("In config file {}", config_path.display()))?;
         }
+        Ok(Self {
+            name: config.template.name,
+            display_name: config.template.display_name,
+            description: config.template.description,
+            author: config.template.author,
+            tags: config.template.tags,
+            version: config.template.version,
+            min_hayaku_version: config.template.min_hayaku_version,
+            homepage: config.template.homepage,
+            answers_file: config.template.answers_file.unwrap_or(true),
+            project_name: config.project_name,
+            env: config.env,
+        })
     }
 
     pub fn try_from_dir(path: &std::path::Path) -> Result<Self> {
@@ -47,19 +164,7 @@ impl TemplateConfig {
         if config_path.exists() {
             let content = std::fs::read_to_string(&config_path)
                 .with_context(|| format!("Failed to read config file {}", config_path.display()))?;
-            let config: ConfigToml = toml::from_str(&content).map_err(|err| {
-                anyhow!(
-                    "Failed to parse config file {}:\n{err}",
-                    config_path.display()
-                )
-            })?;
-            Ok(Self {
-                name: config.template.name,
-                display_name: config.template.display_name,
-                description: config.template.description,
-                author: config.template.author,
-                env: config.env,
-            })
+            Self::parse(&content, &config_path)
         } else {
             let dir_name = path.file_name().and_then(|c| c.to_str()).ok_or_else(|| {
                 anyhow!("Unable to determine directory name for {}", path.display())
@@ -68,3 +173,70 @@ impl TemplateConfig {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(contents: &str) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("hayaku.toml"), contents).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parses_template_metadata() {
+        let dir = write_config(
+            "[template]\nname = \"vite\"\ntags = [\"web\", \"JS\"]\nversion = \"1.2.3\"\nhomepage = \"https://example.com\"\n",
+        );
+
+        let config = TemplateConfig::try_from_dir(dir.path()).unwrap();
+
+        assert_eq!(config.version, Some(Version::new(1, 2, 3)));
+        assert_eq!(config.homepage.as_deref(), Some("https://example.com"));
+        assert!(config.has_tag("web"));
+        assert!(config.has_tag("js"));
+        assert!(!config.has_tag("rust"));
+    }
+
+    #[test]
+    fn checks_project_names() {
+        let dir = write_config(
+            "[template]\nname = \"rust\"\n\n[project_name]\npattern = \"[a-z][a-z0-9_-]*\"\nmessage = \"use lowercase letters\"\n",
+        );
+        let config = TemplateConfig::try_from_dir(dir.path()).unwrap();
+
+        assert!(config.check_project_name("my-crate").is_ok());
+        let err = config.check_project_name("1crate").unwrap_err().to_string();
+        assert!(err.contains("use lowercase letters"), "{err}");
+        // The pattern must match the whole name
+        assert!(config.check_project_name("my crate").is_err());
+        assert!(
+            TemplateConfig::default("x")
+                .check_project_name("any name")
+                .is_ok()
+        );
+
+        let dir = write_config("[template]\nname = \"x\"\n\n[project_name]\npattern = \"[\"\n");
+        assert!(TemplateConfig::try_from_dir(dir.path()).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_versions() {
+        let dir = write_config("[template]\nname = \"x\"\nversion = \"1.2\"\n");
+        assert!(TemplateConfig::try_from_dir(dir.path()).is_err());
+    }
+
+    #[test]
+    fn checks_minimum_hayaku_version() {
+        let mut config = TemplateConfig::default("x");
+        assert!(config.check_hayaku_version().is_ok());
+
+        config.min_hayaku_version = Some(Version::parse(env!("CARGO_PKG_VERSION")).unwrap());
+        assert!(config.check_hayaku_version().is_ok());
+
+        config.min_hayaku_version = Some(Version::new(999, 0, 0));
+        let err = config.check_hayaku_version().unwrap_err().to_string();
+        assert!(err.contains("requires hayaku 999.0.0 or newer"), "{err}");
+    }
+}
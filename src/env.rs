@@ -1,11 +1,14 @@
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Result, anyhow};
-use cliclack;
+use chrono::{Datelike, Local, NaiveDate};
+use heck::{ToKebabCase, ToSnakeCase, ToUpperCamelCase};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use tera::Context as TeraContext;
+use tera::{Context as TeraContext, Tera};
 
-use crate::{config::TemplateConfig, hayaku_context::Hayaku};
+use crate::{config::TemplateConfig, git::GitInfo, settings::HayakuSettings};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -13,6 +16,9 @@ pub enum EnvVarConfig {
     String {
         prompt: String,
         default: Option<String>,
+        /// Hide the input and leave the value out of `.hayaku-answers.toml`
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        secret: bool,
     },
     Choices {
         prompt: String,
@@ -26,46 +32,568 @@ pub enum EnvVarConfig {
     },
 }
 
-fn add_config_env_to_context(config: &TemplateConfig, context: &mut TeraContext) -> Result<()> {
-    if config.env.is_empty() {
-        return Ok(());
+impl EnvVarConfig {
+    pub fn is_secret(&self) -> bool {
+        matches!(self, EnvVarConfig::String { secret: true, .. })
     }
-    for (raw_key, env_cfg) in config.env.iter() {
-        let key = canonical_env_key(raw_key);
-        match &env_cfg {
-            EnvVarConfig::String { prompt, default } => {
-                let mut input = cliclack::input(prompt).required(true);
-                if let Some(default) = default {
-                    input = input.default_input(default);
-                }
-                let result = input.interact::<String>()?;
-                context.insert(&key, &result);
+}
+
+/// Variables that hayaku provides to every template.
+pub const BUILT_IN_VARIABLES: &[&str] = &[
+    "project_name",
+    "PROJECT_NAME",
+    "PROJECT_NAME_SNAKE",
+    "PROJECT_NAME_KEBAB",
+    "PROJECT_NAME_PASCAL",
+    "template_name",
+    "TEMPLATE_NAME",
+    "GIT_USER_NAME",
+    "GIT_USER_EMAIL",
+    "GIT_DEFAULT_BRANCH",
+    HOST_NAMESPACE,
+];
+
+/// The table that holds facts about the machine and the run, e.g.
+/// `HAYAKU.year`. It is the only name that is reserved, so that new facts
+/// can't collide with keys that users already have.
+pub const HOST_NAMESPACE: &str = "HAYAKU";
+
+/// Variables that hayaku provides when they apply, e.g. `GIT_REPO_ROOT` when
+/// the project is created inside a git repository.
+pub const OPTIONAL_BUILT_IN_VARIABLES: &[&str] = &[
+    "GIT_REPO_ROOT",
+    "GIT_REMOTE_URL",
+    "GIT_REMOTE_HOST",
+    "GIT_REMOTE_OWNER",
+    "GIT_REMOTE_REPO",
+];
+
+/// Whether a canonical key is reserved for hayaku's own variables. Such keys
+/// can't be used in `global_env` or a template's `env`.
+pub fn is_reserved(key: &str) -> bool {
+    key == HOST_NAMESPACE
+}
+
+fn check_not_reserved(raw_key: &str, source: &str) -> Result<()> {
+    if is_reserved(&canonical_env_key(raw_key)) {
+        return Err(anyhow!(
+            "`{raw_key}` in {source} is reserved for hayaku's built-in variables; please rename it"
+        ));
+    }
+    Ok(())
+}
+
+/// The branch name used for `GIT_DEFAULT_BRANCH` when git's
+/// `init.defaultBranch` isn't set.
+const FALLBACK_DEFAULT_BRANCH: &str = "main";
+
+/// Facts about the user's machine and the project's destination that are
+/// exposed to templates. The default has fixed, empty values, which keeps
+/// rendering reproducible.
+#[derive(Debug, Clone, Default)]
+pub struct HostInfo {
+    pub git: GitInfo,
+    pub date: NaiveDate,
+    pub user: String,
+    pub hostname: String,
+    pub os: String,
+    pub arch: String,
+    pub hayaku_version: String,
+    /// The absolute path of the directory the template is rendered into
+    pub dest_dir: PathBuf,
+    pub template_dir: PathBuf,
+    /// The process environment, used for `${NAME}` in `global_env` values and
+    /// for `HAYAKU_VAR_*` answers
+    pub environment: HashMap<String, String>,
+}
+
+impl HostInfo {
+    pub fn detect(dest_dir: &Path, template_dir: &Path) -> Self {
+        let absolute =
+            |path: &Path| std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        Self {
+            git: GitInfo::detect(dest_dir),
+            date: Local::now().date_naive(),
+            user: ["USER", "USERNAME"]
+                .iter()
+                .find_map(|name| std::env::var(name).ok())
+                .unwrap_or_default(),
+            hostname: hostname(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            hayaku_version: env!("CARGO_PKG_VERSION").to_string(),
+            dest_dir: absolute(dest_dir),
+            template_dir: absolute(template_dir),
+            environment: std::env::vars().collect(),
+        }
+    }
+
+    fn add_to_context(&self, context: &mut TeraContext) {
+        context.insert(
+            HOST_NAMESPACE,
+            &serde_json::json!({
+                "year": self.date.year(),
+                "date": self.date.format("%Y-%m-%d").to_string(),
+                "user": self.user,
+                "hostname": self.hostname,
+                "os": self.os,
+                "arch": self.arch,
+                "version": self.hayaku_version,
+                "dest_dir": self.dest_dir.display().to_string(),
+                "template_dir": self.template_dir.display().to_string(),
+            }),
+        );
+
+        let git = &self.git;
+        context.insert("GIT_USER_NAME", git.user_name.as_deref().unwrap_or(""));
+        context.insert("GIT_USER_EMAIL", git.user_email.as_deref().unwrap_or(""));
+        context.insert(
+            "GIT_DEFAULT_BRANCH",
+            git.default_branch
+                .as_deref()
+                .unwrap_or(FALLBACK_DEFAULT_BRANCH),
+        );
+        if let Some(root) = &git.repo_root {
+            context.insert("GIT_REPO_ROOT", &root.display().to_string());
+        }
+        if let Some(remote) = &git.remote {
+            context.insert("GIT_REMOTE_URL", &remote.url);
+            context.insert("GIT_REMOTE_HOST", &remote.host);
+            context.insert("GIT_REMOTE_OWNER", &remote.owner);
+            context.insert("GIT_REMOTE_REPO", &remote.repo);
+        }
+    }
+}
+
+fn hostname() -> String {
+    ["HOSTNAME", "COMPUTERNAME"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok())
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .or_else(|| {
+            let output = std::process::Command::new("hostname").output().ok()?;
+            String::from_utf8(output.stdout).ok()
+        })
+        .map(|name| name.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// The prefix of environment variables that answer prompts, e.g.
+/// `HAYAKU_VAR_CRATE_TYPE=lib`.
+pub const ENV_VAR_PREFIX: &str = "HAYAKU_VAR_";
+
+/// Answers to a template's prompts that are known up front. From highest to
+/// lowest precedence: `--var` values, answers from a file, then
+/// `HAYAKU_VAR_*` environment variables.
+#[derive(Debug, Clone, Default)]
+pub struct Answers {
+    /// `--var KEY=VALUE` pairs, which are typed by the variable they answer
+    vars: Vec<(String, String)>,
+    /// Typed answers keyed by the variable names used in `hayaku.toml`
+    values: toml::Table,
+    /// Whether the `HAYAKU_VAR_*` variables in the environment captured by
+    /// [`HostInfo`] answer prompts
+    environment: bool,
+    interactive: bool,
+    /// Use an empty value for variables without an answer or a default
+    placeholders: bool,
+}
+
+impl Answers {
+    /// Prompt for every variable that isn't set with `HAYAKU_VAR_*`.
+    pub fn interactive() -> Self {
+        Self::prompt_missing(toml::Table::new())
+    }
+
+    /// Use the given answers and `HAYAKU_VAR_*` values, and prompt for the
+    /// rest.
+    pub fn prompt_missing(values: toml::Table) -> Self {
+        Self {
+            values,
+            interactive: true,
+            ..Default::default()
+        }
+        .with_environment()
+    }
+
+    /// Use the given answers and fall back to each variable's default instead
+    /// of prompting. The environment is ignored so that the result is
+    /// reproducible.
+    pub fn fixed(values: toml::Table) -> Self {
+        Self {
+            values,
+            interactive: false,
+            ..Default::default()
+        }
+    }
+
+    /// Uses an empty value, rather than failing, for variables that have no
+    /// answer and no default. This suits rendering an old version of a
+    /// template, whose variables may no longer be asked for.
+    pub fn with_placeholders(mut self) -> Self {
+        self.placeholders = true;
+        self
+    }
+
+    /// Adds answers from `--var KEY=VALUE` flags.
+    pub fn with_vars(mut self, vars: Vec<(String, String)>) -> Self {
+        self.vars.extend(vars);
+        self
+    }
+
+    /// Adds answers from a file. Answers given so far take precedence.
+    pub fn with_values(mut self, values: toml::Table) -> Self {
+        for (key, value) in values {
+            self.values.entry(key).or_insert(value);
+        }
+        self
+    }
+
+    /// Takes answers from `HAYAKU_VAR_*` environment variables too.
+    pub fn with_environment(mut self) -> Self {
+        self.environment = true;
+        self
+    }
+
+    /// The `HAYAKU_VAR_*` value for a variable, by canonical name.
+    fn environment_value<'a>(&self, host: &'a HostInfo, key: &str) -> Option<&'a str> {
+        if !self.environment {
+            return None;
+        }
+        host.environment
+            .get(&format!("{ENV_VAR_PREFIX}{key}"))
+            .map(String::as_str)
+    }
+
+    /// The last `--var` given for a variable, matched by canonical name.
+    fn var(&self, key: &str) -> Option<&str> {
+        self.vars
+            .iter()
+            .rev()
+            .find(|(var, _)| canonical_env_key(var) == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Splits a `KEY=VALUE` argument.
+pub fn parse_var_arg(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got `{arg}`")),
+    }
+}
+
+fn parse_bool(raw_key: &str, value: &str) -> Result<toml::Value> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "y" | "1" => Ok(toml::Value::Boolean(true)),
+        "false" | "no" | "n" | "0" => Ok(toml::Value::Boolean(false)),
+        _ => Err(anyhow!(
+            "Invalid value '{value}' for {raw_key}; expected true or false"
+        )),
+    }
+}
+
+/// Types a value given as text, from `--var` or the environment, according to
+/// the variable it answers.
+fn typed_value(raw_key: &str, env_cfg: &EnvVarConfig, value: &str) -> Result<toml::Value> {
+    match env_cfg {
+        EnvVarConfig::Bool { .. } => parse_bool(raw_key, value),
+        _ => Ok(toml::Value::String(value.to_string())),
+    }
+}
+
+/// Types a value given as text for a `global_env` key, keeping the type of
+/// the configured value.
+fn typed_global_value(key: &str, current: &toml::Value, value: &str) -> Result<toml::Value> {
+    Ok(match current {
+        toml::Value::Boolean(_) => parse_bool(key, value)?,
+        toml::Value::Integer(_) => toml::Value::Integer(
+            value
+                .parse()
+                .map_err(|_| anyhow!("Invalid value '{value}' for {key}; expected an integer"))?,
+        ),
+        toml::Value::Float(_) => toml::Value::Float(
+            value
+                .parse()
+                .map_err(|_| anyhow!("Invalid value '{value}' for {key}; expected a number"))?,
+        ),
+        _ => toml::Value::String(value.to_string()),
+    })
+}
+
+/// Checks a preset answer against the variable's declared type.
+fn preset_value(raw_key: &str, env_cfg: &EnvVarConfig, value: &toml::Value) -> Result<toml::Value> {
+    match (env_cfg, value) {
+        (EnvVarConfig::String { .. }, toml::Value::String(_))
+        | (EnvVarConfig::Bool { .. }, toml::Value::Boolean(_)) => Ok(value.clone()),
+        (EnvVarConfig::Choices { choices, .. }, toml::Value::String(choice)) => {
+            if choices.contains(choice) {
+                Ok(value.clone())
+            } else {
+                Err(anyhow!(
+                    "Invalid answer '{choice}' for {raw_key}; expected one of: {}",
+                    choices.join(", ")
+                ))
             }
-            EnvVarConfig::Choices {
-                prompt,
-                choices,
-                default,
-            } => {
-                let choices_tuple: Vec<(String, String, String)> = choices
-                    .iter()
-                    .map(|c| (c.clone(), c.clone(), c.clone()))
-                    .collect();
-                let mut input = cliclack::select(prompt).items(&choices_tuple);
-                if let Some(default) = default {
-                    input = input.initial_value(default.clone())
-                }
-                let result = input.interact()?;
-                context.insert(&key, &result);
+        }
+        (EnvVarConfig::Bool { .. }, _) => Err(anyhow!(
+            "Invalid answer for {raw_key}; expected true or false"
+        )),
+        _ => Err(anyhow!("Invalid answer for {raw_key}; expected a string")),
+    }
+}
+
+fn default_value(raw_key: &str, env_cfg: &EnvVarConfig) -> Result<toml::Value> {
+    match env_cfg {
+        EnvVarConfig::String { default, .. } | EnvVarConfig::Choices { default, .. } => default
+            .clone()
+            .map(toml::Value::String)
+            .ok_or_else(|| anyhow!("No answer or default value for {raw_key}")),
+        EnvVarConfig::Bool { default, .. } => Ok(toml::Value::Boolean(*default)),
+    }
+}
+
+fn placeholder_value(env_cfg: &EnvVarConfig) -> toml::Value {
+    match env_cfg {
+        EnvVarConfig::Choices { choices, .. } => {
+            toml::Value::String(choices.first().cloned().unwrap_or_default())
+        }
+        EnvVarConfig::String { .. } => toml::Value::String(String::new()),
+        EnvVarConfig::Bool { default, .. } => toml::Value::Boolean(*default),
+    }
+}
+
+fn prompt_value(env_cfg: &EnvVarConfig) -> Result<toml::Value> {
+    let value = match env_cfg {
+        EnvVarConfig::String {
+            prompt,
+            secret: true,
+            ..
+        } => toml::Value::String(cliclack::password(prompt).mask('▪').interact()?),
+        EnvVarConfig::String {
+            prompt, default, ..
+        } => {
+            let mut input = cliclack::input(prompt).required(true);
+            if let Some(default) = default {
+                input = input.default_input(default);
+            }
+            toml::Value::String(input.interact::<String>()?)
+        }
+        EnvVarConfig::Choices {
+            prompt,
+            choices,
+            default,
+        } => {
+            let choices_tuple: Vec<(String, String, String)> = choices
+                .iter()
+                .map(|c| (c.clone(), c.clone(), c.clone()))
+                .collect();
+            let mut input = cliclack::select(prompt).items(&choices_tuple);
+            if let Some(default) = default {
+                input = input.initial_value(default.clone())
             }
-            EnvVarConfig::Bool { prompt, default } => {
-                let mut confirm = cliclack::confirm(prompt);
-                if *default {
-                    confirm = confirm.initial_value(*default)
+            toml::Value::String(input.interact()?)
+        }
+        EnvVarConfig::Bool { prompt, default } => {
+            let mut confirm = cliclack::confirm(prompt);
+            if *default {
+                confirm = confirm.initial_value(*default)
+            }
+            toml::Value::Boolean(confirm.interact()?)
+        }
+    };
+    Ok(value)
+}
+
+fn is_template(text: &str) -> bool {
+    text.contains("{{") || text.contains("{%")
+}
+
+/// Replaces `${NAME}` and `${NAME:-fallback}` in `text` with values from
+/// `environment`. Like in a shell, unset variables are replaced with their
+/// fallback, or with nothing. `$${` is a literal `${`, and a `${` without a
+/// closing `}` is kept as is.
+fn interpolate_env(text: &str, environment: &HashMap<String, String>) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            result.push_str(&rest[..start - 1]);
+            result.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        result.push_str(&rest[..start]);
+        let reference = &rest[start + 2..start + end];
+        let (name, fallback) = match reference.split_once(":-") {
+            Some((name, fallback)) => (name, Some(fallback)),
+            None => (reference, None),
+        };
+        match environment.get(name) {
+            Some(value) => result.push_str(value),
+            None => result.push_str(fallback.unwrap_or_default()),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Applies `f` to every string in `value`, including those nested in tables
+/// and arrays.
+fn map_strings(
+    value: &toml::Value,
+    f: &mut impl FnMut(&str) -> Result<String>,
+) -> Result<toml::Value> {
+    Ok(match value {
+        toml::Value::String(text) => toml::Value::String(f(text)?),
+        toml::Value::Array(items) => toml::Value::Array(
+            items
+                .iter()
+                .map(|item| map_strings(item, f))
+                .collect::<Result<_>>()?,
+        ),
+        toml::Value::Table(table) => toml::Value::Table(
+            table
+                .iter()
+                .map(|(key, item)| Ok((key.clone(), map_strings(item, f)?)))
+                .collect::<Result<_>>()?,
+        ),
+        other => other.clone(),
+    })
+}
+
+fn contains_template(value: &toml::Value) -> bool {
+    match value {
+        toml::Value::String(text) => is_template(text),
+        toml::Value::Array(items) => items.iter().any(contains_template),
+        toml::Value::Table(table) => table.values().any(contains_template),
+        _ => false,
+    }
+}
+
+/// Renders the Tera templates in `global_env` values, which may refer to
+/// each other. Every pass renders each value against the results of the
+/// previous pass, until a pass changes nothing.
+fn render_global_templates(
+    templated: &[(String, toml::Value)],
+    context: &mut TeraContext,
+) -> Result<()> {
+    let mut current: Vec<Option<toml::Value>> = vec![None; templated.len()];
+    for _ in 0..=templated.len() {
+        let mut changed = false;
+        for ((key, value), current) in templated.iter().zip(current.iter_mut()) {
+            let rendered = map_strings(value, &mut |text| {
+                if !is_template(text) {
+                    return Ok(text.to_string());
                 }
-                let result = confirm.interact()?;
-                context.insert(&key, &result);
+                Tera::one_off(text, context, false).map_err(|err| {
+                    anyhow!("Failed to render the global_env value for {key}:\n{err:?}")
+                })
+            })?;
+            if current.as_ref() != Some(&rendered) {
+                changed = true;
+                context.insert(key, &rendered);
+                *current = Some(rendered);
+            }
+        }
+        if !changed {
+            return Ok(());
+        }
+    }
+    Err(anyhow!(
+        "The global_env values for {} refer to each other in a cycle",
+        keys_in_cycles(templated).join(", ")
+    ))
+}
+
+/// The keys whose values refer back to themselves, directly or through other
+/// values.
+fn keys_in_cycles(templated: &[(String, toml::Value)]) -> Vec<&str> {
+    let refers_to = |value: &toml::Value, key: &str| {
+        Regex::new(&format!(r"\b{}\b", regex::escape(key)))
+            .is_ok_and(|pattern| pattern.is_match(&value.to_string()))
+    };
+    let references = |from: &toml::Value| -> Vec<usize> {
+        (0..templated.len())
+            .filter(|&to| refers_to(from, &templated[to].0))
+            .collect()
+    };
+    let mut keys = Vec::new();
+    for (index, (key, value)) in templated.iter().enumerate() {
+        let mut seen = vec![false; templated.len()];
+        let mut pending = references(value);
+        while let Some(next) = pending.pop() {
+            if std::mem::replace(&mut seen[next], true) {
+                continue;
             }
+            pending.extend(references(&templated[next].1));
+        }
+        if seen[index] {
+            keys.push(key.as_str());
+        }
+    }
+    keys.sort();
+    keys
+}
+
+/// Renders Tera in a variable's default, so that defaults can refer to
+/// earlier variables, e.g. `default = "{{ GIT_USER_NAME }}"`.
+fn render_default(
+    raw_key: &str,
+    env_cfg: &EnvVarConfig,
+    context: &TeraContext,
+) -> Result<EnvVarConfig> {
+    let mut env_cfg = env_cfg.clone();
+    if let EnvVarConfig::String {
+        default: Some(default),
+        ..
+    }
+    | EnvVarConfig::Choices {
+        default: Some(default),
+        ..
+    } = &mut env_cfg
+        && is_template(default)
+    {
+        *default = Tera::one_off(default, context, false)
+            .map_err(|err| anyhow!("Failed to render the default for {raw_key}:\n{err:?}"))?;
+    }
+    Ok(env_cfg)
+}
+
+fn add_config_env_to_context(
+    config: &TemplateConfig,
+    settings: &HayakuSettings,
+    answers: &Answers,
+    host: &HostInfo,
+    context: &mut TeraContext,
+) -> Result<()> {
+    let template_env = settings.template_env(&config.name);
+    for (raw_key, env_cfg) in config.env.iter() {
+        check_not_reserved(raw_key, "the template's env")?;
+        let env_cfg = &render_default(raw_key, env_cfg, context)?;
+        let key = canonical_env_key(raw_key);
+        let preset = if let Some(value) = answers.var(&key) {
+            Some(typed_value(raw_key, env_cfg, value)?)
+        } else if let Some(value) = answers.values.get(raw_key) {
+            Some(value.clone())
+        } else if let Some(value) = answers.environment_value(host, &key) {
+            Some(typed_value(raw_key, env_cfg, value)?)
+        } else {
+            template_env.and_then(|env| env.get(raw_key)).cloned()
+        };
+        let value = match &preset {
+            Some(value) => preset_value(raw_key, env_cfg, value)?,
+            None if answers.interactive => prompt_value(env_cfg)?,
+            None if answers.placeholders => {
+                default_value(raw_key, env_cfg).unwrap_or_else(|_| placeholder_value(env_cfg))
+            }
+            None => default_value(raw_key, env_cfg)?,
         };
+        context.insert(key, &value);
     }
     Ok(())
 }
@@ -73,22 +601,46 @@ fn add_config_env_to_context(config: &TemplateConfig, context: &mut TeraContext)
 pub fn build_context(
     project_name: &str,
     config: &TemplateConfig,
-    hayaku: &Hayaku,
+    settings: &HayakuSettings,
+    answers: &Answers,
+    host: &HostInfo,
 ) -> Result<TeraContext> {
     let mut context = TeraContext::new();
     context.insert("project_name", project_name);
     context.insert("PROJECT_NAME", project_name);
+    for (key, value) in project_name_variants(project_name) {
+        context.insert(key, &value);
+    }
     context.insert("template_name", &config.name);
     context.insert("TEMPLATE_NAME", &config.name);
+    host.add_to_context(&mut context);
 
-    let global_settings = hayaku.parse_settings()?;
-    if let Some(global_env) = global_settings.global_env {
-        for (key, value) in global_env.iter() {
-            context.insert(&canonical_env_key(key), value);
+    if let Some(global_env) = &settings.global_env {
+        let mut templated = Vec::new();
+        for (raw_key, value) in global_env.iter() {
+            check_not_reserved(raw_key, "global_env")?;
+            let key = canonical_env_key(raw_key);
+            let value = match answers
+                .var(&key)
+                .or_else(|| answers.environment_value(host, &key))
+            {
+                Some(text) => typed_global_value(&key, value, text)?,
+                None => {
+                    let value = map_strings(value, &mut |text| {
+                        Ok(interpolate_env(text, &host.environment))
+                    })?;
+                    if contains_template(&value) {
+                        templated.push((key.clone(), value.clone()));
+                    }
+                    value
+                }
+            };
+            context.insert(key, &value);
         }
+        render_global_templates(&templated, &mut context)?;
     }
 
-    add_config_env_to_context(config, &mut context)?;
+    add_config_env_to_context(config, settings, answers, host, &mut context)?;
 
     Ok(context)
 }
@@ -105,8 +657,22 @@ pub fn canonical_env_key(raw: &str) -> String {
         .collect()
 }
 
+/// The case variants of a project name that are exposed to templates, e.g.
+/// `PROJECT_NAME_SNAKE` for `my_project`.
+pub fn project_name_variants(project_name: &str) -> [(&'static str, String); 3] {
+    [
+        ("PROJECT_NAME_SNAKE", project_name.to_snake_case()),
+        ("PROJECT_NAME_KEBAB", project_name.to_kebab_case()),
+        ("PROJECT_NAME_PASCAL", project_name.to_upper_camel_case()),
+    ]
+}
+
+/// The last component of the destination, after resolving `.` and `..` in
+/// paths that exist.
 pub fn project_name_from_path(dest_path: &Path) -> Result<String> {
     dest_path
+        .canonicalize()
+        .unwrap_or_else(|_| dest_path.to_path_buf())
         .file_name()
         .and_then(|name| name.to_str())
         .map(|s| s.to_string())
@@ -128,9 +694,386 @@ mod tests {
         assert_eq!(canonical_env_key("with-hyphen"), "WITH_HYPHEN");
     }
 
+    #[test]
+    fn project_name_variants_cover_common_cases() {
+        let variants = project_name_variants("my-cool_app");
+        assert_eq!(
+            variants.map(|(_, value)| value),
+            ["my_cool_app", "my-cool-app", "MyCoolApp"]
+        );
+    }
+
+    fn config_with_env(env: &[(&str, EnvVarConfig)]) -> TemplateConfig {
+        let mut config = TemplateConfig::default("demo");
+        config.env = env
+            .iter()
+            .map(|(key, cfg)| (key.to_string(), cfg.clone()))
+            .collect();
+        config
+    }
+
+    #[test]
+    fn fixed_answers_fill_the_context_without_prompting() {
+        let config = config_with_env(&[
+            (
+                "crate_type",
+                EnvVarConfig::Choices {
+                    prompt: "?".to_string(),
+                    choices: vec!["lib".to_string(), "bin".to_string()],
+                    default: Some("bin".to_string()),
+                },
+            ),
+            (
+                "workspace",
+                EnvVarConfig::Bool {
+                    prompt: "?".to_string(),
+                    default: false,
+                },
+            ),
+        ]);
+        let answers = Answers::fixed(toml::toml! { crate_type = "lib" });
+
+        let context = build_context(
+            "demo",
+            &config,
+            &HayakuSettings::default(),
+            &answers,
+            &HostInfo::default(),
+        )
+        .unwrap();
+
+        assert_eq!(context.get("CRATE_TYPE").unwrap(), "lib");
+        assert_eq!(context.get("WORKSPACE").unwrap(), false);
+    }
+
+    #[test]
+    fn fixed_answers_are_type_checked() {
+        let config = config_with_env(&[(
+            "crate_type",
+            EnvVarConfig::Choices {
+                prompt: "?".to_string(),
+                choices: vec!["lib".to_string(), "bin".to_string()],
+                default: None,
+            },
+        )]);
+        let settings = HayakuSettings::default();
+
+        let invalid = Answers::fixed(toml::toml! { crate_type = "dylib" });
+        assert!(build_context("demo", &config, &settings, &invalid, &HostInfo::default()).is_err());
+
+        let missing = Answers::fixed(toml::Table::new());
+        assert!(build_context("demo", &config, &settings, &missing, &HostInfo::default()).is_err());
+
+        let placeholder = missing.with_placeholders();
+        let context = build_context(
+            "demo",
+            &config,
+            &settings,
+            &placeholder,
+            &HostInfo::default(),
+        )
+        .unwrap();
+        assert_eq!(context.get("CRATE_TYPE").unwrap(), "lib");
+    }
+
+    #[test]
+    fn template_settings_preset_answers() {
+        let config = config_with_env(&[(
+            "crate_type",
+            EnvVarConfig::Choices {
+                prompt: "?".to_string(),
+                choices: vec!["lib".to_string(), "bin".to_string()],
+                default: None,
+            },
+        )]);
+        let settings: HayakuSettings =
+            toml::from_str("[templates.demo.env]\ncrate_type = \"lib\"\n").unwrap();
+
+        let context = build_context(
+            "demo",
+            &config,
+            &settings,
+            &Answers::fixed(toml::Table::new()),
+            &HostInfo::default(),
+        )
+        .unwrap();
+        assert_eq!(context.get("CRATE_TYPE").unwrap(), "lib");
+
+        let answers = Answers::fixed(toml::toml! { crate_type = "bin" });
+        let context =
+            build_context("demo", &config, &settings, &answers, &HostInfo::default()).unwrap();
+        assert_eq!(context.get("CRATE_TYPE").unwrap(), "bin");
+    }
+
+    #[test]
+    fn answers_follow_documented_precedence() {
+        let config = config_with_env(&[
+            (
+                "kind",
+                EnvVarConfig::String {
+                    prompt: "?".to_string(),
+                    default: Some("default".to_string()),
+                    secret: false,
+                },
+            ),
+            (
+                "docker",
+                EnvVarConfig::Bool {
+                    prompt: "?".to_string(),
+                    default: false,
+                },
+            ),
+        ]);
+        let settings: HayakuSettings =
+            toml::from_str("[global_env]\nlicense = \"MIT\"\nport = 80\n").unwrap();
+        let environment = [
+            ("HAYAKU_VAR_KIND", "env"),
+            ("HAYAKU_VAR_DOCKER", "yes"),
+            ("HAYAKU_VAR_PORT", "8080"),
+            ("HAYAKU_VAR_LICENSE", "Apache-2.0"),
+            ("UNRELATED", "x"),
+        ]
+        .map(|(key, value)| (key.to_string(), value.to_string()));
+        let host = HostInfo {
+            environment: HashMap::from(environment),
+            ..Default::default()
+        };
+        let answers = |values: toml::Table, vars: &[(&str, &str)]| {
+            Answers::fixed(values).with_environment().with_vars(
+                vars.iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+            )
+        };
+
+        let context = build_context(
+            "demo",
+            &config,
+            &settings,
+            &answers(toml::Table::new(), &[]),
+            &host,
+        )
+        .unwrap();
+        assert_eq!(context.get("KIND").unwrap(), "env");
+        assert_eq!(context.get("DOCKER").unwrap(), true);
+        assert_eq!(context.get("PORT").unwrap(), 8080);
+        assert_eq!(context.get("LICENSE").unwrap(), "Apache-2.0");
+
+        let from_file = toml::toml! { kind = "file" };
+        let context = build_context(
+            "demo",
+            &config,
+            &settings,
+            &answers(from_file.clone(), &[]),
+            &host,
+        )
+        .unwrap();
+        assert_eq!(context.get("KIND").unwrap(), "file");
+
+        let vars = [("kind", "var"), ("license", "MPL-2.0"), ("docker", "no")];
+        let context = build_context(
+            "demo",
+            &config,
+            &settings,
+            &answers(from_file, &vars),
+            &host,
+        )
+        .unwrap();
+        assert_eq!(context.get("KIND").unwrap(), "var");
+        assert_eq!(context.get("DOCKER").unwrap(), false);
+        assert_eq!(context.get("LICENSE").unwrap(), "MPL-2.0");
+
+        let invalid = answers(toml::Table::new(), &[("docker", "maybe")]);
+        assert!(build_context("demo", &config, &settings, &invalid, &host).is_err());
+    }
+
+    #[test]
+    fn exposes_git_info_to_prompt_defaults() {
+        let config = config_with_env(&[(
+            "author",
+            EnvVarConfig::String {
+                prompt: "?".to_string(),
+                default: Some("{{ GIT_USER_NAME }} <{{ GIT_USER_EMAIL }}>".to_string()),
+                secret: false,
+            },
+        )]);
+        let host = HostInfo {
+            git: GitInfo {
+                user_name: Some("Ada".to_string()),
+                user_email: Some("ada@example.com".to_string()),
+                remote: crate::git::Remote::parse("git@github.com:acme/tools.git"),
+                repo_root: Some("/src/tools".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let context = build_context(
+            "demo",
+            &config,
+            &HayakuSettings::default(),
+            &Answers::fixed(toml::Table::new()),
+            &host,
+        )
+        .unwrap();
+
+        assert_eq!(context.get("AUTHOR").unwrap(), "Ada <ada@example.com>");
+        assert_eq!(context.get("GIT_DEFAULT_BRANCH").unwrap(), "main");
+        assert_eq!(context.get("GIT_REMOTE_OWNER").unwrap(), "acme");
+        assert_eq!(context.get("GIT_REMOTE_REPO").unwrap(), "tools");
+
+        let context = build_context(
+            "demo",
+            &config,
+            &HayakuSettings::default(),
+            &Answers::fixed(toml::Table::new()),
+            &HostInfo::default(),
+        )
+        .unwrap();
+        assert_eq!(context.get("AUTHOR").unwrap(), " <>");
+        assert!(context.get("GIT_REPO_ROOT").is_none());
+    }
+
+    #[test]
+    fn exposes_host_info_under_a_reserved_namespace() {
+        let config = config_with_env(&[(
+            "license_header",
+            EnvVarConfig::String {
+                prompt: "?".to_string(),
+                default: Some("(c) {{ HAYAKU.year }} {{ HAYAKU.user }}".to_string()),
+                secret: false,
+            },
+        )]);
+        let host = HostInfo {
+            date: NaiveDate::from_ymd_opt(2024, 3, 9).unwrap(),
+            user: "ada".to_string(),
+            os: "linux".to_string(),
+            dest_dir: "/src/demo".into(),
+            ..Default::default()
+        };
+        let answers = Answers::fixed(toml::Table::new());
+
+        let context =
+            build_context("demo", &config, &HayakuSettings::default(), &answers, &host).unwrap();
+
+        assert_eq!(context.get("LICENSE_HEADER").unwrap(), "(c) 2024 ada");
+        let host_info = &context.get("HAYAKU").unwrap();
+        assert_eq!(host_info["date"], "2024-03-09");
+        assert_eq!(host_info["os"], "linux");
+        assert_eq!(host_info["dest_dir"], "/src/demo");
+
+        let settings = HayakuSettings {
+            global_env: Some(HashMap::from([(
+                "year".to_string(),
+                toml::Value::from(1999),
+            )])),
+            ..Default::default()
+        };
+        let context = build_context("demo", &config, &settings, &answers, &host).unwrap();
+        assert_eq!(context.get("YEAR").unwrap(), 1999);
+        assert_eq!(context.get("HAYAKU").unwrap()["year"], 2024);
+
+        let config = config_with_env(&[(
+            "hayaku",
+            EnvVarConfig::Bool {
+                prompt: "?".to_string(),
+                default: false,
+            },
+        )]);
+        let err = build_context("demo", &config, &HayakuSettings::default(), &answers, &host)
+            .unwrap_err();
+        assert!(err.to_string().contains("reserved"), "{err}");
+    }
+
+    #[test]
+    fn global_env_supports_tables_templates_and_environment() {
+        let settings: HayakuSettings = toml::from_str(
+            r#"
+            [global_env]
+            copyright = "{{ HAYAKU.year }} {{ COMPANY.name }}"
+            notice = "Copyright {{ COPYRIGHT }}"
+            license_dir = "${HOME}/licenses"
+            license = "${LICENSE:-MIT}"
+            literal = "$${HOME} and ${UNCLOSED"
+
+            [global_env.company]
+            name = "Acme"
+            url = "https://${DOMAIN}"
+            "#,
+        )
+        .unwrap();
+        let host = HostInfo {
+            date: NaiveDate::from_ymd_opt(2024, 3, 9).unwrap(),
+            environment: HashMap::from([
+                ("HOME".to_string(), "/home/ada".to_string()),
+                ("DOMAIN".to_string(), "acme.dev".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let config = config_with_env(&[]);
+
+        let context = build_context(
+            "demo",
+            &config,
+            &settings,
+            &Answers::fixed(toml::Table::new()),
+            &host,
+        )
+        .unwrap();
+        let render = |template: &str| Tera::one_off(template, &context, false).unwrap();
+
+        assert_eq!(
+            render("{{ COMPANY.name }} {{ COMPANY.url }}"),
+            "Acme https://acme.dev"
+        );
+        assert_eq!(render("{{ COPYRIGHT }}"), "2024 Acme");
+        assert_eq!(render("{{ NOTICE }}"), "Copyright 2024 Acme");
+        assert_eq!(render("{{ LICENSE_DIR }}"), "/home/ada/licenses");
+        assert_eq!(render("{{ LICENSE }}"), "MIT");
+        assert_eq!(render("{{ LITERAL }}"), "${HOME} and ${UNCLOSED");
+
+        let settings: HayakuSettings =
+            toml::from_str("[global_env]\na = \"{{ B }}a\"\nb = \"{{ A }}b\"\nc = \"{{ A }}c\"\n")
+                .unwrap();
+        let err = build_context(
+            "demo",
+            &config,
+            &settings,
+            &Answers::fixed(toml::Table::new()),
+            &host,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The global_env values for A, B refer to each other in a cycle"
+        );
+    }
+
+    #[test]
+    fn parses_var_arguments() {
+        assert_eq!(
+            parse_var_arg("greeting=a=b").unwrap(),
+            ("greeting".to_string(), "a=b".to_string())
+        );
+        assert!(parse_var_arg("greeting").is_err());
+        assert!(parse_var_arg("=x").is_err());
+    }
+
     #[test]
     fn project_name_extraction() {
         let path = Path::new("/tmp/example");
         assert_eq!(project_name_from_path(path).unwrap(), "example");
+
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("my-project");
+        std::fs::create_dir_all(project.join("src")).unwrap();
+        assert_eq!(
+            project_name_from_path(&project.join("src/..")).unwrap(),
+            "my-project"
+        );
+        assert_eq!(
+            project_name_from_path(&project.join(".")).unwrap(),
+            "my-project"
+        );
     }
 }
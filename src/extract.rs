@@ -0,0 +1,299 @@
+use crate::config::{ConfigToml, TemplateSection};
+use crate::env::project_name_variants;
+use crate::templating;
+use anyhow::{Context, Result, bail};
+use ignore::WalkBuilder;
+use ignore::overrides::OverrideBuilder;
+use indexmap::IndexMap;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubstitutionLocation {
+    Path,
+    Contents,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Substitution {
+    /// The file in the new template, relative to the template root
+    pub file: PathBuf,
+    pub location: SubstitutionLocation,
+    pub from: String,
+    pub to: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExtractReport {
+    pub files: usize,
+    pub substitutions: Vec<Substitution>,
+    /// Files renamed to `*.tera` because they now contain Tera syntax
+    pub tera_files: Vec<PathBuf>,
+    /// Files in the project that were left out because they aren't UTF-8
+    /// text, which templates can't contain
+    pub skipped_files: Vec<PathBuf>,
+}
+
+/// A literal string in the project and the variable that replaces it.
+struct Pattern {
+    literal: String,
+    variable: &'static str,
+}
+
+fn patterns_for(project_name: &str) -> Vec<Pattern> {
+    let mut patterns = vec![Pattern {
+        literal: project_name.to_string(),
+        variable: "PROJECT_NAME",
+    }];
+    for (variable, literal) in project_name_variants(project_name) {
+        if !patterns.iter().any(|p| p.literal == literal) {
+            patterns.push(Pattern { literal, variable });
+        }
+    }
+    // Prefer the longest match when one variant is a prefix of another
+    patterns.sort_by_key(|p| std::cmp::Reverse(p.literal.len()));
+    patterns
+}
+
+/// Replaces every pattern in `input`, returning the new string and how many
+/// times each pattern matched.
+fn replace_patterns(
+    input: &str,
+    patterns: &[Pattern],
+    replacement: impl Fn(&str) -> String,
+) -> (String, HashMap<usize, usize>) {
+    let mut output = String::with_capacity(input.len());
+    let mut counts = HashMap::new();
+    let mut rest = input;
+    'outer: while let Some(c) = rest.chars().next() {
+        for (i, pattern) in patterns.iter().enumerate() {
+            if !pattern.literal.is_empty() && rest.starts_with(&pattern.literal) {
+                output.push_str(&replacement(pattern.variable));
+                *counts.entry(i).or_insert(0) += 1;
+                rest = &rest[pattern.literal.len()..];
+                continue 'outer;
+            }
+        }
+        output.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    (output, counts)
+}
+
+fn contains_tera(contents: &str) -> bool {
+    contents.contains("{{") || contents.contains("{%") || contents.contains("{#")
+}
+
+/// Copies `project_dir` into `template_dir`, replacing the project name and
+/// its case variants with template variables.
+pub fn extract(
+    project_dir: &Path,
+    template_dir: &Path,
+    template_name: &str,
+    project_name: &str,
+) -> Result<ExtractReport> {
+    if !project_dir.is_dir() {
+        bail!("The path {} is not a directory", project_dir.display());
+    }
+    if template_dir.exists() {
+        bail!(
+            "Template directory {} already exists",
+            template_dir.display()
+        );
+    }
+
+    let patterns = patterns_for(project_name);
+    let mut report = ExtractReport::default();
+
+    let mut overrides = OverrideBuilder::new(project_dir);
+    overrides.add("!**/.git")?;
+    overrides.add("!**/hayaku.toml")?;
+    let overrides = overrides.build()?;
+
+    let mut walker = WalkBuilder::new(project_dir);
+    walker
+        .git_ignore(true)
+        .require_git(false)
+        .hidden(false)
+        .overrides(overrides);
+
+    for entry in walker.build() {
+        let entry = entry?;
+        if !entry.file_type().map(|ft| ft.is_file()).unwrap_or(false) {
+            continue;
+        }
+        let rel_path = entry.path().strip_prefix(project_dir)?;
+        let rel_str = rel_path.to_string_lossy();
+        let (new_rel, path_counts) =
+            replace_patterns(&rel_str, &patterns, |var| format!("[{var}]"));
+        let mut new_rel = PathBuf::from(new_rel);
+
+        let bytes = fs::read(entry.path())
+            .with_context(|| format!("Failed to read {}", entry.path().display()))?;
+        let Ok(text) = String::from_utf8(bytes) else {
+            report.skipped_files.push(rel_path.to_path_buf());
+            continue;
+        };
+        // Tera delimiters already in the project are rendered literally
+        let escaped = templating::escape_tera(&text);
+        let (contents, content_counts) =
+            replace_patterns(&escaped, &patterns, |var| format!("{{{{ {var} }}}}"));
+        let was_tera = new_rel.extension().is_some_and(|ext| ext == "tera");
+        if was_tera || contains_tera(&contents) {
+            new_rel.as_mut_os_string().push(".tera");
+            report.tera_files.push(new_rel.clone());
+        }
+
+        for (location, counts) in [
+            (SubstitutionLocation::Path, path_counts),
+            (SubstitutionLocation::Contents, content_counts),
+        ] {
+            let mut counts: Vec<_> = counts.into_iter().collect();
+            counts.sort();
+            for (i, count) in counts {
+                let to = match location {
+                    SubstitutionLocation::Path => format!("[{}]", patterns[i].variable),
+                    SubstitutionLocation::Contents => format!("{{{{ {} }}}}", patterns[i].variable),
+                };
+                report.substitutions.push(Substitution {
+                    file: new_rel.clone(),
+                    location,
+                    from: patterns[i].literal.clone(),
+                    to,
+                    count,
+                });
+            }
+        }
+
+        let dest = template_dir.join(&new_rel);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+        fs::write(&dest, contents)
+            .with_context(|| format!("Failed to write {}", dest.display()))?;
+        report.files += 1;
+    }
+
+    let config = ConfigToml {
+        template: TemplateSection {
+            name: template_name.to_string(),
+            display_name: None,
+            description: Some(format!("Extracted from {project_name}")),
+            author: None,
+            ..Default::default()
+        },
+        project_name: None,
+        env: IndexMap::new(),
+    };
+    config.write_to_dir(template_dir)?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_name_variants_in_paths_and_contents() {
+        let project = tempfile::tempdir().unwrap();
+        let project_dir = project.path().join("my-app");
+        fs::create_dir_all(project_dir.join("src")).unwrap();
+        fs::write(
+            project_dir.join("Cargo.toml"),
+            "[package]\nname = \"my-app\"\n",
+        )
+        .unwrap();
+        fs::write(
+            project_dir.join("src/my_app.rs"),
+            "pub struct MyApp;\nuse my_app::MyApp;\n",
+        )
+        .unwrap();
+        fs::write(project_dir.join("README.md"), "Nothing to see").unwrap();
+
+        let template_dir = tempfile::tempdir().unwrap().path().join("rust-app");
+        let report = extract(&project_dir, &template_dir, "rust-app", "my-app").unwrap();
+
+        assert_eq!(report.files, 3);
+        assert_eq!(
+            fs::read_to_string(template_dir.join("Cargo.toml.tera")).unwrap(),
+            "[package]\nname = \"{{ PROJECT_NAME }}\"\n"
+        );
+        assert_eq!(
+            fs::read_to_string(template_dir.join("src/[PROJECT_NAME_SNAKE].rs.tera")).unwrap(),
+            "pub struct {{ PROJECT_NAME_PASCAL }};\nuse {{ PROJECT_NAME_SNAKE }}::{{ PROJECT_NAME_PASCAL }};\n"
+        );
+        assert_eq!(
+            fs::read_to_string(template_dir.join("README.md")).unwrap(),
+            "Nothing to see"
+        );
+        assert!(
+            report
+                .substitutions
+                .iter()
+                .any(|s| s.location == SubstitutionLocation::Contents
+                    && s.from == "MyApp"
+                    && s.count == 2)
+        );
+
+        let config = crate::config::TemplateConfig::try_from_dir(&template_dir).unwrap();
+        assert_eq!(config.name, "rust-app");
+    }
+
+    #[test]
+    fn escapes_existing_tera_syntax() {
+        let project = tempfile::tempdir().unwrap();
+        fs::write(project.path().join("ci.yml"), "run: ${{ matrix.os }}\n").unwrap();
+
+        let template_dir = tempfile::tempdir().unwrap().path().join("ci");
+        extract(project.path(), &template_dir, "ci", "unused").unwrap();
+
+        let escaped = fs::read_to_string(template_dir.join("ci.yml.tera")).unwrap();
+        let rendered = tera::Tera::one_off(&escaped, &tera::Context::new(), false).unwrap();
+        assert_eq!(rendered, "run: ${{ matrix.os }}\n");
+    }
+
+    #[test]
+    fn skips_binary_files_so_the_template_can_be_created() {
+        let project = tempfile::tempdir().unwrap();
+        let project_dir = project.path().join("demo");
+        fs::create_dir_all(&project_dir).unwrap();
+        fs::write(project_dir.join("main.txt"), "demo\n").unwrap();
+        fs::write(
+            project_dir.join("logo.png"),
+            [0x89, b'P', b'N', b'G', 0xff, 0x00],
+        )
+        .unwrap();
+
+        let template_dir = tempfile::tempdir().unwrap().path().join("demo");
+        let report = extract(&project_dir, &template_dir, "demo", "demo").unwrap();
+        assert_eq!(report.skipped_files, vec![PathBuf::from("logo.png")]);
+        assert!(!template_dir.join("logo.png").exists());
+
+        let config = crate::config::TemplateConfig::try_from_dir(&template_dir).unwrap();
+        let context = crate::env::build_context(
+            "other",
+            &config,
+            &crate::settings::HayakuSettings::default(),
+            &crate::env::Answers::interactive(),
+            &crate::env::HostInfo::default(),
+        )
+        .unwrap();
+        let dest_dir = tempfile::tempdir().unwrap();
+        crate::templating::create_project(&template_dir, dest_dir.path(), &context).unwrap();
+        assert_eq!(
+            fs::read_to_string(dest_dir.path().join("main.txt")).unwrap(),
+            "other\n"
+        );
+    }
+
+    #[test]
+    fn refuses_to_overwrite_an_existing_template() {
+        let project = tempfile::tempdir().unwrap();
+        let template_dir = tempfile::tempdir().unwrap();
+        assert!(extract(project.path(), template_dir.path(), "x", "x").is_err());
+    }
+}
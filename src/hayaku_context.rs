@@ -1,8 +1,13 @@
+use crate::built_in;
 use crate::config::TemplateConfig;
+use crate::settings::{
+    HayakuSettings, LayeredSettings, SYSTEM_SETTINGS_ENV_VAR, SYSTEM_SETTINGS_FILE, TemplatePath,
+    find_project_settings, resolve_settings_path, rewrite_settings_paths,
+};
 use anyhow::{Result, anyhow};
-use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
+    ffi::OsString,
     path::{Path, PathBuf},
 };
 
@@ -17,104 +22,453 @@ pub struct TemplateEntry {
     pub config: TemplateConfig,
     pub path: PathBuf,
     pub origin: TemplateOrigin,
+    /// The name of the template directory this was loaded from, used to
+    /// refer to it unambiguously as `<namespace>/<name>`
+    pub namespace: String,
+    /// The directories between the template directory and this template,
+    /// e.g. `["rust"]` for `templates/rust/cli`
+    pub category: Vec<String>,
+    /// Refers to this template within its template directory. This is its
+    /// name, or its path there when several templates in it share the name.
+    pub id: String,
+}
+
+impl TemplateEntry {
+    pub fn qualified_id(&self) -> String {
+        format!("{}/{}", self.namespace, self.id)
+    }
+
+    /// The path of this template relative to its template directory, e.g.
+    /// `rust/cli`.
+    fn relative_path(&self) -> String {
+        let mut components = self.category.clone();
+        components.push(dir_name(&self.path));
+        components.join("/")
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub struct HayakuSettings {
-    pub global_env: Option<HashMap<String, toml::Value>>,
+/// A directory that contains templates, along with the templates found in it.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct TemplateRoot {
+    pub name: String,
+    pub path: PathBuf,
+    pub templates: HashMap<String, TemplateEntry>,
 }
 
-impl HayakuSettings {
-    pub fn write_to_file(&self, path: &Path) -> Result<()> {
-        // Ensure parent directory exists
-        if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent)?;
+/// A template name that is declared by more than one local template. The
+/// first template is the one that is used for the plain name.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct DuplicateTemplate {
+    pub name: String,
+    /// The qualified id and path of each template with the name
+    pub templates: Vec<(String, PathBuf)>,
+}
+
+/// Where hayaku keeps its settings, local templates and caches: either all in
+/// one directory, like `~/.hayaku`, or in the XDG base directories.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HayakuDirs {
+    /// Holds `hayaku.settings.toml`. Relative paths in the settings are
+    /// resolved against it.
+    pub config: PathBuf,
+    pub templates: PathBuf,
+    pub cache: PathBuf,
+}
+
+impl HayakuDirs {
+    const TEMPLATE_DIR: &str = "templates";
+    const CACHE_DIR: &str = "cache";
+    const LEGACY_DIR: &str = ".hayaku";
+
+    /// Everything in `dir`.
+    pub fn single(dir: &Path) -> Self {
+        Self {
+            config: dir.to_path_buf(),
+            templates: dir.join(Self::TEMPLATE_DIR),
+            cache: dir.join(Self::CACHE_DIR),
+        }
+    }
+
+    /// `$XDG_CONFIG_HOME/hayaku`, `$XDG_DATA_HOME/hayaku/templates` and
+    /// `$XDG_CACHE_HOME/hayaku`, with the XDG defaults under `home`.
+    pub fn xdg(home: &Path, var: impl Fn(&str) -> Option<OsString>) -> Self {
+        let base = |name: &str, default: &str| {
+            var(name)
+                .map(PathBuf::from)
+                .filter(|path| path.is_absolute())
+                .unwrap_or_else(|| home.join(default))
+                .join("hayaku")
+        };
+        Self {
+            config: base("XDG_CONFIG_HOME", ".config"),
+            templates: base("XDG_DATA_HOME", ".local/share").join(Self::TEMPLATE_DIR),
+            cache: base("XDG_CACHE_HOME", ".cache"),
+        }
+    }
+
+    /// Uses `HAYAKU_DIRECTORY` if it is set. Otherwise an existing XDG config
+    /// directory wins over an existing `~/.hayaku`, and new installs use the
+    /// XDG directories on Linux and `~/.hayaku` elsewhere.
+    fn resolve(home: &Path, var: impl Fn(&str) -> Option<OsString>) -> Self {
+        if let Some(dir) = var("HAYAKU_DIRECTORY") {
+            return Self::single(Path::new(&dir));
+        }
+        let xdg = Self::xdg(home, &var);
+        let legacy = Self::single(&home.join(Self::LEGACY_DIR));
+        if xdg.config.is_dir() {
+            xdg
+        } else if legacy.config.is_dir() || !cfg!(target_os = "linux") {
+            legacy
+        } else {
+            xdg
+        }
+    }
+
+    /// Moves the settings and templates from `self` to `to`. Relative paths in
+    /// the settings are rewritten, since they were relative to the old config
+    /// directory. The cache is removed rather than moved, since it is recreated
+    /// as needed, and the old config directory is removed if nothing else is
+    /// left in it. If the settings can't be written, the templates are moved
+    /// back.
+    pub fn migrate_to(&self, to: &HayakuDirs) -> Result<()> {
+        let settings = self.config.join(Hayaku::SETTINGS_FILE);
+        let new_settings = to.config.join(Hayaku::SETTINGS_FILE);
+        for (from, to) in [(&settings, &new_settings), (&self.templates, &to.templates)] {
+            if from.exists() && to.exists() {
+                return Err(anyhow!(
+                    "Can't move {} to {}, which already exists",
+                    from.display(),
+                    to.display()
+                ));
+            }
+        }
+        let contents = if settings.exists() {
+            let raw = std::fs::read_to_string(&settings)
+                .map_err(|err| anyhow!("Failed to read {}:\n{err}", settings.display()))?;
+            Some(rewrite_settings_paths(&raw, |path| {
+                self.moved_path(to, path)
+            })?)
+        } else {
+            None
+        };
+
+        let move_templates = self.templates.exists();
+        if move_templates {
+            move_dir(&self.templates, &to.templates)?;
         }
-        let toml_string = toml::to_string_pretty(self)
-            .map_err(|err| anyhow!("Failed to serialize settings to TOML:\n{err}"))?;
-        std::fs::write(path, toml_string)
-            .map_err(|err| anyhow!("Failed to write settings to {}:\n{err}", path.display()))?;
+        if let Some(contents) = contents {
+            let written = std::fs::create_dir_all(&to.config)
+                .and_then(|()| std::fs::write(&new_settings, contents));
+            if let Err(err) = written {
+                if move_templates {
+                    move_dir(&to.templates, &self.templates)?;
+                }
+                return Err(anyhow!(
+                    "Failed to write {}:\n{err}",
+                    new_settings.display()
+                ));
+            }
+            std::fs::remove_file(&settings)?;
+        }
+        if self.cache.exists() {
+            std::fs::remove_dir_all(&self.cache)?;
+        }
+        // Fails, and keeps the directory, if anything else is in it
+        let _ = std::fs::remove_dir(&self.config);
         Ok(())
     }
+
+    /// What a relative path in the settings should become once they are moved
+    /// to `to`, or `None` if it doesn't need to change.
+    fn moved_path(&self, to: &HayakuDirs, path: &str) -> Option<String> {
+        if path.starts_with('~') || Path::new(path).is_absolute() {
+            return None;
+        }
+        let old = self.config.join(path);
+        let new = match old.strip_prefix(&self.templates) {
+            Ok(rest) => to.templates.join(rest),
+            Err(_) => old,
+        };
+        Some(new.display().to_string())
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Hayaku {
-    hayaku_dir: PathBuf,
-    local_template_dir: PathBuf,
+    dirs: HayakuDirs,
     built_in_template_dir: PathBuf,
     settings_config_path: PathBuf,
+    /// `None` when there is no system settings layer, as in tests
+    system_settings_path: Option<PathBuf>,
+    /// Where the project settings are looked for from
+    project_dir: PathBuf,
+    /// The directories from `HAYAKU_TEMPLATE_PATH`
+    template_path: Vec<PathBuf>,
+    template_roots: Vec<TemplateRoot>,
     local_templates: HashMap<String, TemplateEntry>,
     built_in_templates: HashMap<String, TemplateEntry>,
+    duplicates: Vec<DuplicateTemplate>,
 }
 
 impl Hayaku {
-    const TEMPLATE_DIR: &str = "templates";
     const SETTINGS_FILE: &str = "hayaku.settings.toml";
+    const LOCAL_NAMESPACE: &str = "local";
+    const BUILT_IN_NAMESPACE: &str = "built-in";
 
-    fn hayaku_dir_from_env() -> Result<PathBuf> {
-        if let Ok(dir) = std::env::var("HAYAKU_DIRECTORY") {
-            Ok(dir.into())
-        } else {
-            Ok(std::env::home_dir()
-                .ok_or_else(|| anyhow!("Could not determine home directory"))?
-                .join(".hayaku"))
+    fn home_dir() -> Result<PathBuf> {
+        std::env::home_dir().ok_or_else(|| anyhow!("Could not determine home directory"))
+    }
+
+    /// An environment variable, treating an empty value as unset.
+    fn env_var(name: &str) -> Option<OsString> {
+        std::env::var_os(name).filter(|value| !value.is_empty())
+    }
+
+    fn dirs_from_env() -> Result<HayakuDirs> {
+        Ok(HayakuDirs::resolve(&Self::home_dir()?, Self::env_var))
+    }
+
+    /// The XDG directories to offer moving to, when hayaku uses `~/.hayaku`
+    /// on Linux because it was set up before XDG directories were supported.
+    pub fn xdg_migration(&self) -> Result<Option<HayakuDirs>> {
+        let home = Self::home_dir()?;
+        if !cfg!(target_os = "linux")
+            || Self::env_var("HAYAKU_DIRECTORY").is_some()
+            || self.dirs != HayakuDirs::single(&home.join(HayakuDirs::LEGACY_DIR))
+        {
+            return Ok(None);
         }
+        Ok(Some(HayakuDirs::xdg(&home, Self::env_var)))
+    }
+
+    fn system_settings_path_from_env() -> PathBuf {
+        std::env::var_os(SYSTEM_SETTINGS_ENV_VAR)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(SYSTEM_SETTINGS_FILE))
+    }
+
+    /// Template directories from the colon-separated `HAYAKU_TEMPLATE_PATH`.
+    fn template_path_from_env() -> Vec<PathBuf> {
+        std::env::var_os("HAYAKU_TEMPLATE_PATH")
+            .map(|paths| {
+                std::env::split_paths(&paths)
+                    .filter(|path| !path.as_os_str().is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Loads everything from `hayaku_dir`, without looking at the environment.
+    /// Project settings are found from `project_dir`.
+    pub fn try_new_from_dir(
+        hayaku_dir: &Path,
+        system_settings_path: Option<&Path>,
+        project_dir: &Path,
+    ) -> Result<Self> {
+        Self::try_new_with_template_path(
+            &HayakuDirs::single(hayaku_dir),
+            Vec::new(),
+            system_settings_path.map(Path::to_path_buf),
+            project_dir,
+        )
     }
-    pub fn try_new_from_dir(hayaku_dir: &Path) -> Result<Self> {
-        let local_template_dir = hayaku_dir.join(Self::TEMPLATE_DIR);
+
+    /// Loads templates from `template_path`, then the local template directory,
+    /// then the `template_paths` setting that applies to `project_dir`. Earlier
+    /// directories take precedence.
+    fn try_new_with_template_path(
+        dirs: &HayakuDirs,
+        template_path: Vec<PathBuf>,
+        system_settings_path: Option<PathBuf>,
+        project_dir: &Path,
+    ) -> Result<Self> {
+        let hayaku_dir = dirs.config.as_path();
+        let local_template_dir = dirs.templates.clone();
         let settings_config_path = hayaku_dir.join(Self::SETTINGS_FILE);
-        let built_in_template_dir = built_in_templates_dir();
+        let built_in_template_dir = built_in::dir_in(&dirs.cache);
+        let settings = LayeredSettings::load(
+            system_settings_path.as_deref(),
+            &settings_config_path,
+            project_dir,
+        )?
+        .settings()?;
 
-        let local_templates = load_templates_from_dir(&local_template_dir, TemplateOrigin::Local)?;
+        let mut roots: Vec<(String, PathBuf)> = Vec::new();
+        let env_roots = template_path
+            .iter()
+            .map(|path| (namespace_for(path), path.clone()));
+        let configured = settings.template_paths.iter().map(|entry| match entry {
+            TemplatePath::Path(path) => {
+                let path = resolve_settings_path(hayaku_dir, path);
+                (namespace_for(&path), path)
+            }
+            TemplatePath::Named { name, path } => {
+                (name.clone(), resolve_settings_path(hayaku_dir, path))
+            }
+        });
+        for (mut name, path) in env_roots
+            .chain([(
+                Self::LOCAL_NAMESPACE.to_string(),
+                local_template_dir.clone(),
+            )])
+            .chain(configured)
+        {
+            if roots.iter().any(|(_, existing)| existing == &path) {
+                continue;
+            }
+            // Keep namespaces unique so that qualified ids stay unambiguous
+            let base = name.clone();
+            let mut suffix = 2;
+            while name == Self::BUILT_IN_NAMESPACE || roots.iter().any(|(n, _)| n == &name) {
+                name = format!("{base}-{suffix}");
+                suffix += 1;
+            }
+            roots.push((name, path));
+        }
+
+        let mut template_roots = Vec::new();
+        let mut local_templates: HashMap<String, TemplateEntry> = HashMap::new();
+        let mut duplicates: Vec<DuplicateTemplate> = Vec::new();
+        for (name, path) in roots {
+            let mut entries = load_templates_from_dir(&path, TemplateOrigin::Local, &name)?;
+            assign_ids(&mut entries);
+            for entry in &entries {
+                let id = &entry.config.name;
+                if let Some(winner) = local_templates.get(id) {
+                    let template = (entry.qualified_id(), entry.path.clone());
+                    match duplicates.iter_mut().find(|d| &d.name == id) {
+                        Some(duplicate) => duplicate.templates.push(template),
+                        None => duplicates.push(DuplicateTemplate {
+                            name: id.clone(),
+                            templates: vec![(winner.qualified_id(), winner.path.clone()), template],
+                        }),
+                    }
+                } else {
+                    local_templates.insert(id.clone(), entry.clone());
+                }
+            }
+            template_roots.push(TemplateRoot {
+                name,
+                path,
+                templates: by_id(entries),
+            });
+        }
 
-        let built_in_templates =
-            load_templates_from_dir(&built_in_template_dir, TemplateOrigin::BuiltIn)?;
+        // Built-in templates are only extracted once their files are needed
+        let mut built_in: Vec<TemplateEntry> = built_in::configs(&built_in_template_dir)?
+            .into_iter()
+            .map(|(path, config)| TemplateEntry {
+                id: config.name.clone(),
+                config,
+                path,
+                origin: TemplateOrigin::BuiltIn,
+                namespace: Self::BUILT_IN_NAMESPACE.to_string(),
+                category: Vec::new(),
+            })
+            .collect();
+        assign_ids(&mut built_in);
+        let built_in_templates = by_id(built_in);
 
         Ok(Self {
-            hayaku_dir: hayaku_dir.to_path_buf(),
+            dirs: dirs.clone(),
             settings_config_path,
-            local_template_dir,
+            system_settings_path,
+            project_dir: project_dir.to_path_buf(),
             built_in_template_dir,
+            template_path,
+            template_roots,
             local_templates,
             built_in_templates,
+            duplicates,
         })
     }
+
     pub fn try_new() -> Result<Self> {
-        Self::try_new_from_dir(&Self::hayaku_dir_from_env()?)
+        Self::try_new_with_template_path(
+            &Self::dirs_from_env()?,
+            Self::template_path_from_env(),
+            Some(Self::system_settings_path_from_env()),
+            Path::new("."),
+        )
+    }
+
+    /// Reloads the templates with the `template_paths` from the project
+    /// settings that apply to `project_dir`, when they aren't the ones that
+    /// were loaded.
+    pub fn for_project(&self, project_dir: &Path) -> Result<Self> {
+        if find_project_settings(project_dir) == find_project_settings(&self.project_dir) {
+            return Ok(self.clone());
+        }
+        Self::try_new_with_template_path(
+            &self.dirs,
+            self.template_path.clone(),
+            self.system_settings_path.clone(),
+            project_dir,
+        )
+    }
+
+    /// The user's settings file, found without loading any settings.
+    pub fn user_settings_path() -> Result<PathBuf> {
+        Ok(Self::dirs_from_env()?.config.join(Self::SETTINGS_FILE))
     }
 
     pub fn settings_config_path(&self) -> &Path {
         &self.settings_config_path
     }
 
+    /// The settings for the directory hayaku was loaded for, usually the
+    /// current directory. See [`Self::parse_settings_for`].
     pub fn parse_settings(&self) -> Result<HayakuSettings> {
-        if self.settings_config_path.exists() {
-            let raw = std::fs::read_to_string(&self.settings_config_path)?;
-            let config: HayakuSettings = toml::from_str(&raw).map_err(|err| {
-                anyhow!(
-                    "Failed to parse settings file {}:\n{err}",
-                    self.settings_config_path.display()
-                )
-            })?;
-            Ok(config)
-        } else {
-            Ok(HayakuSettings::default())
-        }
+        self.parse_settings_for(&self.project_dir)
+    }
+
+    /// The system, user and project settings that apply to `project_dir`,
+    /// merged in that order of precedence.
+    pub fn parse_settings_for(&self, project_dir: &Path) -> Result<HayakuSettings> {
+        self.settings_layers(project_dir)?.settings()
+    }
+
+    pub fn settings_layers(&self, project_dir: &Path) -> Result<LayeredSettings> {
+        LayeredSettings::load(
+            self.system_settings_path.as_deref(),
+            &self.settings_config_path,
+            project_dir,
+        )
+    }
+
+    pub fn dirs(&self) -> &HayakuDirs {
+        &self.dirs
     }
 
     pub fn hayaku_dir(&self) -> &Path {
-        &self.hayaku_dir
+        &self.dirs.config
     }
 
     pub fn local_template_dir(&self) -> &Path {
-        &self.local_template_dir
+        &self.dirs.templates
     }
 
     pub fn built_in_template_dir(&self) -> &Path {
         &self.built_in_template_dir
     }
 
+    /// The directory with `template`'s files. Built-in templates are extracted
+    /// to it first, unless this version has already been extracted.
+    pub fn template_files<'a>(&self, template: &'a TemplateEntry) -> Result<&'a Path> {
+        if template.origin == TemplateOrigin::BuiltIn {
+            built_in::extract_to(&self.dirs.cache)?;
+        }
+        Ok(&template.path)
+    }
+
+    /// Every local template directory, in order of precedence.
+    pub fn template_roots(&self) -> &[TemplateRoot] {
+        &self.template_roots
+    }
+
+    /// Local templates from all template directories. When several directories
+    /// contain a template with the same name, the first one wins.
     pub fn templates(&self) -> &HashMap<String, TemplateEntry> {
         &self.local_templates
     }
@@ -149,33 +503,333 @@ impl Hayaku {
         combined
     }
 
+    /// Looks up a template by name, or by `<namespace>/<name>` to pick a
+    /// specific one when several directories declare the same name.
     pub fn get(&self, id: &str) -> Option<&TemplateEntry> {
+        if let Some((namespace, name)) = id.split_once('/') {
+            if namespace == Self::BUILT_IN_NAMESPACE {
+                return self.built_in_templates.get(name);
+            }
+            return self
+                .template_roots
+                .iter()
+                .find(|root| root.name == namespace)
+                .and_then(|root| root.templates.get(name));
+        }
         self.local_templates
             .get(id)
             .or_else(|| self.built_in_templates.get(id))
     }
 
+    /// Template names that are declared by more than one local template.
+    /// Local templates that override a built-in one are not included.
+    pub fn duplicates(&self) -> &[DuplicateTemplate] {
+        &self.duplicates
+    }
+
+    /// Every template that can be selected, including ones that are shadowed
+    /// by a template with the same name, paired with the id that selects it.
+    pub fn selectable_templates(&self) -> Vec<(String, &TemplateEntry)> {
+        let by_display_name = |a: &&TemplateEntry, b: &&TemplateEntry| {
+            let a_name = a.config.display_name.as_ref().unwrap_or(&a.config.name);
+            let b_name = b.config.display_name.as_ref().unwrap_or(&b.config.name);
+            a_name.cmp(b_name)
+        };
+        let mut entries: Vec<&TemplateEntry> = self.built_in_templates.values().collect();
+        entries.sort_by(by_display_name);
+        for root in &self.template_roots {
+            let mut templates: Vec<_> = root.templates.values().collect();
+            templates.sort_by(by_display_name);
+            entries.extend(templates);
+        }
+
+        entries
+            .iter()
+            .map(|entry| {
+                let shared = entries
+                    .iter()
+                    .filter(|other| other.config.name == entry.config.name)
+                    .count()
+                    > 1;
+                let id = if shared {
+                    entry.qualified_id()
+                } else {
+                    entry.config.name.clone()
+                };
+                (id, *entry)
+            })
+            .collect()
+    }
+
+    /// Copies a template into the local template directory so that it can be
+    /// edited, returning the new template's path.
+    pub fn copy_to_local(&self, template: &TemplateEntry) -> Result<PathBuf> {
+        let dest = self.local_template_dir().join(&template.config.name);
+        if dest.exists() {
+            return Err(anyhow!("{} already exists", dest.display()));
+        }
+        copy_dir(self.template_files(template)?, &dest)?;
+        Ok(dest)
+    }
+
     pub fn no_local_templates(&self) -> bool {
         self.local_templates.is_empty()
     }
 }
 
+/// Copies `from` to `to`, including hidden and ignored files.
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    for entry in ignore::WalkBuilder::new(from)
+        .standard_filters(false)
+        .build()
+    {
+        let entry = entry?;
+        let target = to.join(entry.path().strip_prefix(from)?);
+        if entry.file_type().is_some_and(|ft| ft.is_dir()) {
+            std::fs::create_dir_all(&target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)
+                .map_err(|err| anyhow!("Failed to copy {}:\n{err}", entry.path().display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Moves a directory, copying it and removing the original when it can't be
+/// renamed, such as across file systems.
+fn move_dir(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match std::fs::rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::CrossesDevices => {
+            if let Err(err) = copy_dir(from, to) {
+                let _ = std::fs::remove_dir_all(to);
+                return Err(err);
+            }
+            std::fs::remove_dir_all(from)
+                .map_err(|err| anyhow!("Failed to remove {}:\n{err}", from.display()))
+        }
+        Err(err) => Err(anyhow!(
+            "Failed to move {} to {}:\n{err}",
+            from.display(),
+            to.display()
+        )),
+    }
+}
+
+/// The namespace of a template directory that wasn't given an explicit name.
+fn namespace_for(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// Gives the templates of one template directory unique ids. Templates that
+/// share a name are referred to by their path instead, as is any template
+/// whose name is the same as one of those paths.
+fn assign_ids(entries: &mut [TemplateEntry]) {
+    let mut by_path: Vec<String> = Vec::new();
+    for entry in entries.iter() {
+        if entries
+            .iter()
+            .filter(|other| other.config.name == entry.config.name)
+            .count()
+            > 1
+        {
+            by_path.push(entry.relative_path());
+        }
+    }
+    for entry in entries.iter_mut() {
+        let path = entry.relative_path();
+        if by_path.contains(&path) || by_path.contains(&entry.config.name) {
+            entry.id = path;
+        }
+    }
+}
+
+fn by_id(entries: Vec<TemplateEntry>) -> HashMap<String, TemplateEntry> {
+    entries
+        .into_iter()
+        .map(|entry| (entry.id.clone(), entry))
+        .collect()
+}
+
+/// Loads every template in `dir`, sorted by path so that the order is the
+/// same on every platform.
+///
+/// A directory with a `hayaku.toml` is a template. A directory without one is
+/// a category when any directory below it has a `hayaku.toml`, and is
+/// otherwise a template without a config.
+fn load_templates_from_dir(
+    dir: &Path,
+    origin: TemplateOrigin,
+    namespace: &str,
+) -> Result<Vec<TemplateEntry>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    load_children(dir, origin, namespace, &[])
+}
+
+fn load_children(
+    dir: &Path,
+    origin: TemplateOrigin,
+    namespace: &str,
+    category: &[String],
+) -> Result<Vec<TemplateEntry>> {
+    let mut templates = Vec::new();
+    for path in subdirectories(dir)? {
+        if !path.join("hayaku.toml").is_file() && contains_config(&path) {
+            let mut nested = category.to_vec();
+            nested.push(dir_name(&path));
+            templates.extend(load_children(&path, origin, namespace, &nested)?);
+        } else {
+            templates.push(load_template(path, origin, namespace, category.to_vec())?);
+        }
+    }
+    Ok(templates)
+}
+
+fn load_template(
+    path: PathBuf,
+    origin: TemplateOrigin,
+    namespace: &str,
+    category: Vec<String>,
+) -> Result<TemplateEntry> {
+    let config = TemplateConfig::try_from_dir(&path)?;
+    Ok(TemplateEntry {
+        id: config.name.clone(),
+        config,
+        path,
+        origin,
+        namespace: namespace.to_string(),
+        category,
+    })
+}
+
+/// How many directories below a category the nearest `hayaku.toml` may be.
+const MAX_CATEGORY_DEPTH: usize = 4;
+
+/// Whether `dir` or any directory below it has a `hayaku.toml`.
+/// Only looks this many directories deep, skipping hidden and git-ignored
+/// directories, so that a config-less template with a large `node_modules` or
+/// `target` isn't searched in full.
+fn contains_config(dir: &Path) -> bool {
+    ignore::WalkBuilder::new(dir)
+        .max_depth(Some(MAX_CATEGORY_DEPTH))
+        .require_git(false)
+        .build()
+        .filter_map(|entry| entry.ok())
+        .any(|entry| entry.file_name() == "hayaku.toml")
+}
+
+/// The non-hidden subdirectories of `dir`, sorted by path.
+fn subdirectories(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() && !dir_name(&path).starts_with('.') {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+fn dir_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn copies_built_in_templates_to_local() {
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let hayaku = Hayaku::try_new_from_dir(dir.path(), None, dir.path()).expect("init");
+        let built_in = hayaku.built_in_templates()["rust"].clone();
+
+        let copy = hayaku.copy_to_local(&built_in).expect("copy template");
+
+        assert_eq!(copy, dir.path().join("templates/rust"));
+        assert!(copy.join("hayaku.toml").is_file());
+        assert!(copy.join("src").is_dir());
+        let hayaku = Hayaku::try_new_from_dir(dir.path(), None, dir.path()).expect("reload");
+        assert_eq!(hayaku.get("rust").unwrap().origin, TemplateOrigin::Local);
+        assert!(hayaku.copy_to_local(&built_in).is_err());
+    }
+
+    #[test]
+    fn resolves_xdg_and_legacy_directories() {
+        let home = tempfile::tempdir().expect("create temp dir");
+        let home = home.path();
+        let no_vars = |_: &str| None;
+        let xdg = HayakuDirs::xdg(home, no_vars);
+        assert_eq!(xdg.config, home.join(".config/hayaku"));
+        assert_eq!(xdg.templates, home.join(".local/share/hayaku/templates"));
+        assert_eq!(xdg.cache, home.join(".cache/hayaku"));
+        let custom = HayakuDirs::xdg(home, |name| {
+            (name == "XDG_CONFIG_HOME").then(|| OsString::from("/etc/xdg"))
+        });
+        assert_eq!(custom.config, Path::new("/etc/xdg/hayaku"));
+
+        let legacy = HayakuDirs::single(&home.join(".hayaku"));
+        let fresh = if cfg!(target_os = "linux") {
+            &xdg
+        } else {
+            &legacy
+        };
+        assert_eq!(&HayakuDirs::resolve(home, no_vars), fresh);
+
+        std::fs::create_dir_all(legacy.templates.join("mine")).expect("create legacy");
+        std::fs::write(
+            legacy.config.join("hayaku.settings.toml"),
+            "editor = \"vim\"\nprojects_dir = \"projects\" # mine\ntemplate_paths = [\"templates/mine\", \"~/team\"]\n",
+        )
+        .expect("write settings");
+        std::fs::create_dir_all(&legacy.cache).expect("create cache");
+        assert_eq!(HayakuDirs::resolve(home, no_vars), legacy);
+        assert_eq!(
+            HayakuDirs::resolve(home, |name| {
+                (name == "HAYAKU_DIRECTORY").then(|| OsString::from("/opt/hayaku"))
+            }),
+            HayakuDirs::single(Path::new("/opt/hayaku"))
+        );
+
+        legacy.migrate_to(&xdg).expect("migrate");
+        assert_eq!(
+            std::fs::read_to_string(xdg.config.join("hayaku.settings.toml")).unwrap(),
+            format!(
+                "editor = \"vim\"\nprojects_dir = \"{}\" # mine\ntemplate_paths = [\"{}\", \"~/team\"]\n",
+                legacy.config.join("projects").display(),
+                xdg.templates.join("mine").display()
+            )
+        );
+        assert!(xdg.templates.join("mine").is_dir());
+        assert!(!legacy.config.exists());
+        assert_eq!(HayakuDirs::resolve(home, no_vars), xdg);
+    }
+
     #[test]
     fn uses_provided_template_directory() {
         let dir = tempfile::tempdir().expect("create temp dir");
 
-        let templates = Hayaku::try_new_from_dir(dir.path()).expect("init local templates");
+        let templates =
+            Hayaku::try_new_from_dir(dir.path(), None, dir.path()).expect("init local templates");
 
         assert_eq!(templates.hayaku_dir(), dir.path());
         assert_eq!(templates.local_template_dir(), dir.path().join("templates"));
         assert!(templates.templates().is_empty());
-        assert_eq!(
-            templates.built_in_template_dir().file_name(),
-            Some(std::ffi::OsStr::new("built_in"))
+        assert!(
+            templates
+                .built_in_template_dir()
+                .starts_with(dir.path().join("cache"))
         );
     }
 
@@ -193,7 +847,8 @@ mod tests {
         )
         .expect("write config");
 
-        let templates = Hayaku::try_new_from_dir(dir.path()).expect("init local templates");
+        let templates =
+            Hayaku::try_new_from_dir(dir.path(), None, dir.path()).expect("init local templates");
 
         assert_eq!(templates.templates().len(), 2);
         assert!(templates.get("alpha-template").is_some());
@@ -204,11 +859,31 @@ mod tests {
         assert_eq!(alpha.path, template_a);
     }
 
+    #[test]
+    fn ignores_configs_in_ignored_directories() {
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let web = dir.path().join("templates/web");
+        std::fs::create_dir_all(web.join("node_modules/pkg")).expect("create package");
+        std::fs::write(web.join(".gitignore"), "node_modules/\n").expect("write gitignore");
+        std::fs::write(
+            web.join("node_modules/pkg/hayaku.toml"),
+            "[template]\nname = \"pkg\"",
+        )
+        .expect("write config");
+
+        let templates =
+            Hayaku::try_new_from_dir(dir.path(), None, dir.path()).expect("init local templates");
+
+        assert_eq!(templates.get("web").unwrap().path, web);
+        assert!(templates.get("pkg").is_none());
+    }
+
     #[test]
     fn loads_built_in_templates() {
         let dir = tempfile::tempdir().expect("create temp dir");
 
-        let templates = Hayaku::try_new_from_dir(dir.path()).expect("init templates");
+        let templates =
+            Hayaku::try_new_from_dir(dir.path(), None, dir.path()).expect("init templates");
 
         assert!(!templates.built_in_templates().is_empty());
         assert!(
@@ -219,6 +894,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn extracts_built_in_templates_when_their_files_are_needed() {
+        let dir = tempfile::tempdir().expect("create temp dir");
+
+        let hayaku =
+            Hayaku::try_new_from_dir(dir.path(), None, dir.path()).expect("init templates");
+        assert!(!hayaku.built_in_template_dir().exists());
+
+        let rust = &hayaku.built_in_templates()["rust"];
+        let files = hayaku.template_files(rust).unwrap();
+        assert_eq!(files, hayaku.built_in_template_dir().join("rust"));
+        assert!(files.join("hayaku.toml").is_file());
+    }
+
     #[test]
     fn local_templates_override_built_in() {
         let dir = tempfile::tempdir().expect("create temp dir");
@@ -233,7 +922,8 @@ mod tests {
         )
         .expect("write local template config");
 
-        let templates = Hayaku::try_new_from_dir(dir.path()).expect("init templates");
+        let templates =
+            Hayaku::try_new_from_dir(dir.path(), None, dir.path()).expect("init templates");
 
         let template = templates.get("rust").expect("rust template exists");
         assert_eq!(template.path, local_rust);
@@ -241,43 +931,190 @@ mod tests {
     }
 
     #[test]
-    fn empty_when_no_subdirectories() {
-        let templates = Hayaku::try_new_from_dir(tempfile::tempdir().unwrap().path())
-            .expect("init local templates");
-        assert!(templates.templates().is_empty());
+    fn searches_template_paths_in_order() {
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let write_template = |root: &Path, name: &str, description: &str| {
+            let template = root.join(name);
+            std::fs::create_dir_all(&template).expect("create template");
+            std::fs::write(
+                template.join("hayaku.toml"),
+                format!("[template]\nname = \"{name}\"\ndescription = \"{description}\""),
+            )
+            .expect("write config");
+        };
+        let env_root = dir.path().join("env");
+        let team_root = dir.path().join("team");
+        write_template(&env_root, "shared", "from env");
+        write_template(&dir.path().join("templates"), "shared", "from local");
+        write_template(&dir.path().join("templates"), "personal", "from local");
+        write_template(&team_root, "personal", "from team");
+        write_template(&team_root, "team-only", "from team");
+        std::fs::write(
+            dir.path().join("hayaku.settings.toml"),
+            "template_paths = [{ name = \"team\", path = \"team\" }]",
+        )
+        .expect("write settings");
+
+        let hayaku = Hayaku::try_new_with_template_path(
+            &HayakuDirs::single(dir.path()),
+            vec![env_root.clone()],
+            None,
+            dir.path(),
+        )
+        .expect("init templates");
+
+        let roots: Vec<&Path> = hayaku
+            .template_roots()
+            .iter()
+            .map(|root| root.path.as_path())
+            .collect();
+        assert_eq!(
+            roots,
+            vec![
+                env_root.as_path(),
+                dir.path().join("templates").as_path(),
+                team_root.as_path()
+            ]
+        );
+        let description = |id: &str| hayaku.get(id).unwrap().config.description.clone();
+        assert_eq!(description("shared").as_deref(), Some("from env"));
+        assert_eq!(description("personal").as_deref(), Some("from local"));
+        assert_eq!(description("team-only").as_deref(), Some("from team"));
+        assert_eq!(hayaku.templates().len(), 3);
+
+        assert_eq!(
+            hayaku.get("team/personal").unwrap().path,
+            team_root.join("personal")
+        );
+        assert_eq!(
+            hayaku.get("env/shared").unwrap().path,
+            env_root.join("shared")
+        );
+        assert_eq!(
+            hayaku.get("local/shared").unwrap().path,
+            dir.path().join("templates/shared")
+        );
+        assert!(hayaku.get("team/shared").is_none());
     }
-}
 
-fn load_templates_from_dir(
-    dir: &Path,
-    origin: TemplateOrigin,
-) -> Result<HashMap<String, TemplateEntry>> {
-    let mut templates = HashMap::new();
+    #[test]
+    fn loads_template_paths_from_the_project_settings() {
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let project = dir.path().join("work/project");
+        let project_templates = dir.path().join("work/templates/service");
+        std::fs::create_dir_all(&project).expect("create project");
+        std::fs::create_dir_all(&project_templates).expect("create template");
+        std::fs::write(
+            project_templates.join("hayaku.toml"),
+            "[template]\nname = \"service\"",
+        )
+        .expect("write config");
+        std::fs::create_dir_all(dir.path().join("work/.hayaku")).expect("create settings dir");
+        std::fs::write(
+            dir.path().join("work/.hayaku/settings.toml"),
+            "template_paths = [\"../templates\"]",
+        )
+        .expect("write settings");
 
-    if !dir.exists() {
-        return Ok(templates);
+        let hayaku = Hayaku::try_new_from_dir(&dir.path().join("home"), None, dir.path())
+            .expect("init templates");
+        assert!(hayaku.get("service").is_none());
+
+        let hayaku = hayaku.for_project(&project).expect("reload templates");
+        assert_eq!(
+            hayaku.get("service").unwrap().path.canonicalize().unwrap(),
+            project_templates.canonicalize().unwrap()
+        );
     }
 
-    for entry in std::fs::read_dir(dir)? {
-        let entry = entry?;
-        if entry.path().is_dir() {
-            let path = entry.path();
-            let config = TemplateConfig::try_from_dir(&path)?;
-            let id = config.name.clone();
-            templates.insert(
-                id,
-                TemplateEntry {
-                    config,
-                    path,
-                    origin,
-                },
+    #[test]
+    fn reports_duplicate_template_names() {
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let templates = dir.path().join("templates");
+        for dir_name in ["b-rust", "a-rust", "rust"] {
+            std::fs::create_dir_all(templates.join(dir_name)).expect("create template");
+            std::fs::write(
+                templates.join(dir_name).join("hayaku.toml"),
+                "[template]\nname = \"rust\"",
+            )
+            .expect("write config");
+        }
+
+        let hayaku =
+            Hayaku::try_new_from_dir(dir.path(), None, dir.path()).expect("init templates");
+
+        assert_eq!(
+            hayaku.duplicates(),
+            &[DuplicateTemplate {
+                name: "rust".to_string(),
+                templates: vec![
+                    ("local/a-rust".to_string(), templates.join("a-rust")),
+                    ("local/b-rust".to_string(), templates.join("b-rust")),
+                    ("local/rust".to_string(), templates.join("rust")),
+                ],
+            }]
+        );
+        assert_eq!(hayaku.get("rust").unwrap().path, templates.join("a-rust"));
+        for dir_name in ["a-rust", "b-rust", "rust"] {
+            assert_eq!(
+                hayaku.get(&format!("local/{dir_name}")).unwrap().path,
+                templates.join(dir_name)
             );
         }
+
+        let selectable: Vec<String> = hayaku
+            .selectable_templates()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert!(selectable.contains(&"local/a-rust".to_string()));
+        assert!(selectable.contains(&"local/b-rust".to_string()));
+        assert!(selectable.contains(&"local/rust".to_string()));
+        assert!(selectable.contains(&"built-in/rust".to_string()));
+        assert_eq!(
+            hayaku.get("built-in/rust").unwrap().origin,
+            TemplateOrigin::BuiltIn
+        );
     }
 
-    Ok(templates)
-}
+    #[test]
+    fn discovers_templates_in_categories() {
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let templates = dir.path().join("templates");
+        for (rel, name) in [
+            ("rust/cli", "rust-cli"),
+            ("rust/lib", "rust-lib"),
+            ("web/js/vite", "vite"),
+        ] {
+            std::fs::create_dir_all(templates.join(rel)).expect("create template");
+            std::fs::write(
+                templates.join(rel).join("hayaku.toml"),
+                format!("[template]\nname = \"{name}\""),
+            )
+            .expect("write config");
+        }
+        // Subdirectories of a template are part of it, even with a config
+        std::fs::create_dir_all(templates.join("rust/cli/src")).expect("create src");
+        // A directory without any config below it is a template itself
+        std::fs::create_dir_all(templates.join("web/plain/src")).expect("create plain");
 
-fn built_in_templates_dir() -> PathBuf {
-    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("built_in")
+        let hayaku =
+            Hayaku::try_new_from_dir(dir.path(), None, dir.path()).expect("init templates");
+
+        let category = |id: &str| hayaku.get(id).expect(id).category.join("/");
+        assert_eq!(hayaku.templates().len(), 4);
+        assert_eq!(category("rust-cli"), "rust");
+        assert_eq!(category("rust-lib"), "rust");
+        assert_eq!(category("vite"), "web/js");
+        assert_eq!(category("plain"), "web");
+        assert!(hayaku.get("web").is_none());
+    }
+
+    #[test]
+    fn empty_when_no_subdirectories() {
+        let dir = tempfile::tempdir().unwrap();
+        let templates =
+            Hayaku::try_new_from_dir(dir.path(), None, dir.path()).expect("init local templates");
+        assert!(templates.templates().is_empty());
+    }
 }
@@ -0,0 +1,153 @@
+use crate::env::{EnvVarConfig, canonical_env_key};
+use crate::hayaku_context::{Hayaku, TemplateEntry, TemplateOrigin};
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// The output of `hayaku list --format json|toml`.
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplateListing {
+    pub templates: Vec<ListedTemplate>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ListedTemplate {
+    /// The id that selects this template with `hayaku create -t`
+    pub id: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// `local` or `built-in`
+    pub origin: &'static str,
+    pub namespace: String,
+    pub category: Vec<String>,
+    pub path: PathBuf,
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// A local template with the same name as a built-in one
+    pub shadows_builtin: bool,
+    /// Another template with the same name is used for the plain name
+    pub shadowed: bool,
+    pub variables: Vec<ListedVariable>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ListedVariable {
+    /// The key in `hayaku.toml`
+    pub name: String,
+    /// The name the variable has in templates
+    pub key: String,
+    #[serde(flatten)]
+    pub config: EnvVarConfig,
+}
+
+impl TemplateListing {
+    /// Lists every selectable template that has all of `tags`.
+    pub fn new(hayaku: &Hayaku, tags: &[String]) -> Self {
+        let templates = hayaku
+            .selectable_templates()
+            .into_iter()
+            .filter(|(_, entry)| tags.iter().all(|tag| entry.config.has_tag(tag)))
+            .map(|(id, entry)| ListedTemplate::new(hayaku, id, entry))
+            .collect();
+        Self { templates }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|err| anyhow!("Failed to serialize templates to JSON:\n{err}"))
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string_pretty(self)
+            .map_err(|err| anyhow!("Failed to serialize templates to TOML:\n{err}"))
+    }
+}
+
+impl ListedTemplate {
+    fn new(hayaku: &Hayaku, id: String, entry: &TemplateEntry) -> Self {
+        let config = &entry.config;
+        let winner = hayaku.get(&config.name);
+        Self {
+            id,
+            name: config.name.clone(),
+            display_name: config.display_name.clone(),
+            description: config.description.clone(),
+            origin: match entry.origin {
+                TemplateOrigin::Local => "local",
+                TemplateOrigin::BuiltIn => "built-in",
+            },
+            namespace: entry.namespace.clone(),
+            category: entry.category.clone(),
+            path: entry.path.clone(),
+            tags: config.tags.clone(),
+            version: config.version.as_ref().map(|version| version.to_string()),
+            shadows_builtin: entry.origin == TemplateOrigin::Local
+                && hayaku.built_in_templates().contains_key(&config.name),
+            shadowed: winner.is_some_and(|winner| winner.path != entry.path),
+            variables: config
+                .env
+                .iter()
+                .map(|(name, config)| ListedVariable {
+                    name: name.clone(),
+                    key: canonical_env_key(name),
+                    config: config.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    fn write(dir: &Path, rel: &str, contents: &str) {
+        let path = dir.join(rel);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn lists_templates_with_variables() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "templates/rust/hayaku.toml",
+            "[template]\nname = \"rust\"\ntags = [\"cli\"]\n\n[env.crate_type]\ntype = \"choices\"\nprompt = \"Kind?\"\nchoices = [\"lib\", \"bin\"]\n\n[env.author]\ntype = \"string\"\nprompt = \"Name?\"\n",
+        );
+        let hayaku = Hayaku::try_new_from_dir(dir.path(), None, dir.path()).unwrap();
+
+        let listing = TemplateListing::new(&hayaku, &["CLI".to_string()]);
+        assert_eq!(listing.templates.len(), 1);
+        let rust = &listing.templates[0];
+        assert_eq!(rust.id, "local/rust");
+        assert_eq!(rust.origin, "local");
+        assert!(rust.shadows_builtin);
+        assert!(!rust.shadowed);
+
+        let json: serde_json::Value = serde_json::from_str(&listing.to_json().unwrap()).unwrap();
+        assert_eq!(
+            json["templates"][0]["variables"][0],
+            serde_json::json!({
+                "name": "crate_type",
+                "key": "CRATE_TYPE",
+                "type": "choices",
+                "prompt": "Kind?",
+                "choices": ["lib", "bin"],
+                "default": null,
+            })
+        );
+
+        let toml: toml::Table = listing.to_toml().unwrap().parse().unwrap();
+        assert_eq!(
+            toml["templates"][0]["variables"][1]["type"].as_str(),
+            Some("string")
+        );
+    }
+}
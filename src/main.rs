@@ -1,10 +1,21 @@
+mod add;
+mod answers_file;
+mod built_in;
 mod cli;
 mod config;
 mod env;
+mod extract;
 mod git;
 mod hayaku_context;
+mod listing;
+mod merge;
 pub use hayaku_context::Hayaku;
+mod new_template;
+mod settings;
+mod template_test;
 mod templating;
+mod update;
+mod validate;
 
 fn main() {
     cli::run().unwrap_or_else(|err| {
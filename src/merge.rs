@@ -0,0 +1,146 @@
+use similar::{DiffTag, TextDiff};
+use std::ops::Range;
+
+/// The result of merging two sets of changes to the same file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Merge {
+    pub text: String,
+    /// Number of regions that were changed differently on both sides, which
+    /// are written with conflict markers
+    pub conflicts: usize,
+}
+
+/// A change to the lines `base` of the base text.
+#[derive(Debug)]
+struct Hunk<'a> {
+    base: Range<usize>,
+    lines: &'a [&'a str],
+    ours: bool,
+}
+
+fn hunks<'a>(diff: &'a TextDiff<'a, 'a, 'a, str>, ours: bool) -> Vec<Hunk<'a>> {
+    diff.ops()
+        .iter()
+        .filter(|op| op.tag() != DiffTag::Equal)
+        .map(|op| Hunk {
+            base: op.old_range(),
+            lines: &diff.new_slices()[op.new_range()],
+            ours,
+        })
+        .collect()
+}
+
+/// Applies `hunks`, which must be sorted and lie within `range`, to the base
+/// lines in `range`.
+fn apply(base: &[&str], range: Range<usize>, hunks: &[&Hunk]) -> String {
+    let mut text = String::new();
+    let mut pos = range.start;
+    for hunk in hunks {
+        text.extend(base[pos..hunk.base.start].iter().copied());
+        text.extend(hunk.lines.iter().copied());
+        pos = hunk.base.end;
+    }
+    text.extend(base[pos..range.end].iter().copied());
+    text
+}
+
+fn push_side(text: &mut String, side: &str) {
+    text.push_str(side);
+    if !side.is_empty() && !side.ends_with('\n') {
+        text.push('\n');
+    }
+}
+
+/// Merges the changes from `base` to `ours` and from `base` to `theirs`, line
+/// by line. Changes that touch the same lines are only merged when both sides
+/// made the same change; otherwise both versions are kept between
+/// `<<<<<<< ours_label` and `>>>>>>> theirs_label` markers.
+pub fn merge3(base: &str, ours: &str, theirs: &str, ours_label: &str, theirs_label: &str) -> Merge {
+    let ours_diff = TextDiff::from_lines(base, ours);
+    let theirs_diff = TextDiff::from_lines(base, theirs);
+    let base_lines = ours_diff.old_slices();
+
+    let mut all: Vec<Hunk> = hunks(&ours_diff, true);
+    all.extend(hunks(&theirs_diff, false));
+    all.sort_by_key(|hunk| (hunk.base.start, hunk.base.end));
+
+    let mut merge = Merge {
+        text: String::new(),
+        conflicts: 0,
+    };
+    let mut pos = 0;
+    let mut i = 0;
+    while i < all.len() {
+        // Group hunks that overlap or touch
+        let start = all[i].base.start;
+        let mut end = all[i].base.end;
+        let mut j = i + 1;
+        while j < all.len() && all[j].base.start <= end {
+            end = end.max(all[j].base.end);
+            j += 1;
+        }
+        let group = &all[i..j];
+        merge.text.extend(base_lines[pos..start].iter().copied());
+
+        let ours_hunks: Vec<&Hunk> = group.iter().filter(|hunk| hunk.ours).collect();
+        let theirs_hunks: Vec<&Hunk> = group.iter().filter(|hunk| !hunk.ours).collect();
+        let ours_text = apply(base_lines, start..end, &ours_hunks);
+        let theirs_text = apply(base_lines, start..end, &theirs_hunks);
+        if ours_hunks.is_empty() || ours_text == theirs_text {
+            merge.text.push_str(&theirs_text);
+        } else if theirs_hunks.is_empty() {
+            merge.text.push_str(&ours_text);
+        } else {
+            merge.conflicts += 1;
+            merge.text.push_str(&format!("<<<<<<< {ours_label}\n"));
+            push_side(&mut merge.text, &ours_text);
+            merge.text.push_str("=======\n");
+            push_side(&mut merge.text, &theirs_text);
+            merge.text.push_str(&format!(">>>>>>> {theirs_label}\n"));
+        }
+
+        pos = end;
+        i = j;
+    }
+    merge.text.extend(base_lines[pos..].iter().copied());
+    merge
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_changes_to_different_lines() {
+        let base = "a\nb\nc\nd\ne\n";
+        let ours = "a\nB\nc\nd\ne\n";
+        let theirs = "a\nb\nc\nd\nE\nf\n";
+
+        let merge = merge3(base, ours, theirs, "project", "template");
+
+        assert_eq!(merge.text, "a\nB\nc\nd\nE\nf\n");
+        assert_eq!(merge.conflicts, 0);
+    }
+
+    #[test]
+    fn identical_changes_do_not_conflict() {
+        let merge = merge3("a\nb\n", "a\nc\n", "a\nc\n", "project", "template");
+        assert_eq!(merge.text, "a\nc\n");
+        assert_eq!(merge.conflicts, 0);
+    }
+
+    #[test]
+    fn marks_conflicting_changes() {
+        let base = "a\nb\nc\n";
+        let ours = "a\nours\nc\n";
+        let theirs = "a\ntheirs\nc";
+
+        let merge = merge3(base, ours, theirs, "project", "template");
+
+        assert_eq!(
+            merge.text,
+            "a\n<<<<<<< project\nours\nc\n=======\ntheirs\nc\n>>>>>>> template\n"
+        );
+        assert_eq!(merge.conflicts, 1);
+    }
+}
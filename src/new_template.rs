@@ -0,0 +1,131 @@
+use crate::config::ConfigToml;
+use crate::env::{EnvVarConfig, canonical_env_key};
+use crate::templating;
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// Creates a new template directory containing `config` as its `hayaku.toml`
+/// and an example `README.md.tera` that uses each of its variables. Fails if
+/// `template_dir` already exists.
+pub fn scaffold(template_dir: &Path, config: &ConfigToml) -> Result<()> {
+    if let Some(parent) = template_dir.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    std::fs::create_dir(template_dir).with_context(|| {
+        format!(
+            "Failed to create template directory {}",
+            template_dir.display()
+        )
+    })?;
+    config.write_to_dir(template_dir)?;
+
+    let readme_path = template_dir.join("README.md.tera");
+    std::fs::write(&readme_path, example_readme(config))
+        .with_context(|| format!("Failed to write {}", readme_path.display()))?;
+    Ok(())
+}
+
+fn example_readme(config: &ConfigToml) -> String {
+    let mut readme = String::from("# {{ PROJECT_NAME }}\n");
+    if let Some(description) = &config.template.description {
+        readme.push_str(&format!("\n{}\n", templating::escape_tera(description)));
+    }
+    if !config.env.is_empty() {
+        readme.push_str("\n## Options\n\n");
+        for (raw_key, env_cfg) in config.env.iter() {
+            let key = canonical_env_key(raw_key);
+            let line = match env_cfg {
+                EnvVarConfig::Bool { .. } => {
+                    format!("- {raw_key}: {{% if {key} %}}yes{{% else %}}no{{% endif %}}\n")
+                }
+                _ => format!("- {raw_key}: {{{{ {key} }}}}\n"),
+            };
+            readme.push_str(&line);
+        }
+    }
+    readme
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{TemplateConfig, TemplateSection};
+    use indexmap::IndexMap;
+
+    #[test]
+    fn scaffolds_config_and_readme() {
+        let dir = tempfile::tempdir().unwrap();
+        let template_dir = dir.path().join("svc");
+        let config = ConfigToml {
+            template: TemplateSection {
+                name: "svc".to_string(),
+                display_name: Some("Service".to_string()),
+                description: Some("A small service".to_string()),
+                author: None,
+                ..Default::default()
+            },
+            project_name: None,
+            env: IndexMap::from([
+                (
+                    "port".to_string(),
+                    EnvVarConfig::String {
+                        prompt: "Port?".to_string(),
+                        default: Some("8080".to_string()),
+                        secret: false,
+                    },
+                ),
+                (
+                    "docker".to_string(),
+                    EnvVarConfig::Bool {
+                        prompt: "Use docker?".to_string(),
+                        default: false,
+                    },
+                ),
+            ]),
+        };
+
+        scaffold(&template_dir, &config).unwrap();
+
+        let loaded = TemplateConfig::try_from_dir(&template_dir).unwrap();
+        assert_eq!(loaded.display_name.as_deref(), Some("Service"));
+        assert_eq!(
+            loaded.env.keys().collect::<Vec<_>>(),
+            vec!["port", "docker"]
+        );
+
+        let readme = std::fs::read_to_string(template_dir.join("README.md.tera")).unwrap();
+        let mut context = tera::Context::new();
+        context.insert("PROJECT_NAME", "demo");
+        context.insert("PORT", "8080");
+        context.insert("DOCKER", &true);
+        let rendered = tera::Tera::one_off(&readme, &context, false).unwrap();
+        assert_eq!(
+            rendered,
+            "# demo\n\nA small service\n\n## Options\n\n- port: 8080\n- docker: yes\n"
+        );
+
+        assert!(scaffold(&template_dir, &config).is_err());
+    }
+
+    #[test]
+    fn keeps_tera_syntax_in_the_description() {
+        let config = ConfigToml {
+            template: TemplateSection {
+                name: "svc".to_string(),
+                description: Some("Uses {{ handlebars }}, {% raw %} and {% endraw %}".to_string()),
+                ..Default::default()
+            },
+            project_name: None,
+            env: IndexMap::new(),
+        };
+
+        let mut context = tera::Context::new();
+        context.insert("PROJECT_NAME", "demo");
+        let rendered = tera::Tera::one_off(&example_readme(&config), &context, false).unwrap();
+        assert_eq!(
+            rendered,
+            "# demo\n\nUses {{ handlebars }}, {% raw %} and {% endraw %}\n"
+        );
+    }
+}
@@ -0,0 +1,921 @@
+use crate::env::{canonical_env_key, is_reserved};
+use anyhow::{Result, anyhow};
+use globset::GlobBuilder;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item, Table, TableLike};
+
+/// An entry in `template_paths`, either a plain path or a path with a
+/// namespace name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TemplatePath {
+    Path(PathBuf),
+    Named { name: String, path: PathBuf },
+}
+
+/// Settings for one template, from `[templates.<name>]`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct TemplateSettings {
+    /// Answers to the template's prompts, keyed by their names in
+    /// `hayaku.toml`. These variables are not prompted for.
+    #[serde(default, skip_serializing_if = "toml::Table::is_empty")]
+    pub env: toml::Table,
+    /// Keys this version of hayaku doesn't know about, kept so that writing
+    /// the settings doesn't drop them
+    #[serde(flatten)]
+    pub other: toml::Table,
+}
+
+/// The environment variable that selects a profile, like `--profile`.
+pub const PROFILE_ENV_VAR: &str = "HAYAKU_PROFILE";
+
+/// A `[[profiles.<name>.match]]` rule.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileMatch {
+    /// A glob such as `~/work/**`, matched against the project directory
+    pub path: String,
+}
+
+/// A `[profiles.<name>]` section, holding settings that override the
+/// top-level ones when the profile is used.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct Profile {
+    /// Project directories the profile is used for when none is selected
+    #[serde(default, rename = "match", skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<ProfileMatch>,
+    #[serde(flatten)]
+    pub settings: toml::Table,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct HayakuSettings {
+    pub global_env: Option<HashMap<String, toml::Value>>,
+    /// Additional template directories, searched in order after the local
+    /// template directory
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub template_paths: Vec<TemplatePath>,
+    /// The command used by `hayaku edit`, e.g. `nvim -p`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub editor: Option<String>,
+    /// The template that is selected first in the `create` picker
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_template: Option<String>,
+    /// Where `create` puts projects that are given as a bare name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub projects_dir: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub templates: IndexMap<String, TemplateSettings>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub profiles: IndexMap<String, Profile>,
+    /// Keys this version of hayaku doesn't know about, kept so that writing
+    /// the settings doesn't drop them
+    #[serde(flatten)]
+    pub other: toml::Table,
+}
+
+impl HayakuSettings {
+    const DEFAULT_EDITOR: &str = "code";
+
+    /// The top-level settings, for checking the keys given to `hayaku config`
+    const KEYS: [&str; 7] = [
+        "global_env",
+        "template_paths",
+        "editor",
+        "default_template",
+        "projects_dir",
+        "templates",
+        "profiles",
+    ];
+    const TEMPLATE_KEYS: [&str; 1] = ["env"];
+    /// The settings a profile can override. `template_paths` is read before
+    /// a profile is known, so it can't be one of them.
+    const PROFILE_KEYS: [&str; 6] = [
+        "match",
+        "global_env",
+        "editor",
+        "default_template",
+        "projects_dir",
+        "templates",
+    ];
+
+    /// The editor command and its arguments, from the `editor` setting,
+    /// `$VISUAL` or `$EDITOR`, in that order.
+    pub fn editor_command(&self) -> Result<Vec<String>> {
+        let env_editor = |name| std::env::var(name).ok().filter(|v: &String| !v.is_empty());
+        let editor = self
+            .editor
+            .clone()
+            .or_else(|| env_editor("VISUAL"))
+            .or_else(|| env_editor("EDITOR"))
+            .unwrap_or_else(|| Self::DEFAULT_EDITOR.to_string());
+        match shlex::split(&editor) {
+            Some(command) if !command.is_empty() => Ok(command),
+            _ => Err(anyhow!("Invalid editor command `{editor}`")),
+        }
+    }
+
+    /// Preset answers for the template named `name`.
+    pub fn template_env(&self, name: &str) -> Option<&toml::Table> {
+        self.templates.get(name).map(|settings| &settings.env)
+    }
+
+    /// The settings as a TOML table, including unknown keys.
+    fn to_table(&self) -> Result<toml::Table> {
+        toml::Table::try_from(self)
+            .map_err(|err| anyhow!("Failed to serialize settings to TOML:\n{err}"))
+    }
+
+    /// Every value that is set, keyed by its dotted path, e.g.
+    /// `templates.rust.env.crate_type`. Arrays are listed as one value.
+    pub fn entries(&self) -> Result<Vec<(String, toml::Value)>> {
+        fn flatten(prefix: &str, table: &toml::Table, entries: &mut Vec<(String, toml::Value)>) {
+            for (key, value) in table {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                match value {
+                    toml::Value::Table(table) => flatten(&path, table, entries),
+                    value => entries.push((path, value.clone())),
+                }
+            }
+        }
+        let mut entries = Vec::new();
+        flatten("", &self.to_table()?, &mut entries);
+        Ok(entries)
+    }
+
+    /// The value at a dotted path such as `editor` or `global_env.company.name`.
+    pub fn get(&self, key: &str) -> Result<Option<toml::Value>> {
+        let path = Self::check_key(key)?;
+        let mut value = toml::Value::Table(self.to_table()?);
+        for segment in path {
+            match value {
+                toml::Value::Table(mut table) => match table.remove(segment) {
+                    Some(next) => value = next,
+                    None => return Ok(None),
+                },
+                _ => return Ok(None),
+            }
+        }
+        Ok(Some(value))
+    }
+
+    /// Sets the value at a dotted path, creating tables along the way. The
+    /// value must have the type the setting expects.
+    pub fn set(&mut self, key: &str, value: toml::Value) -> Result<()> {
+        let path = Self::check_key(key)?;
+        let (last, parents) = path.split_last().expect("keys have at least one segment");
+        let mut table = self.to_table()?;
+        let mut current = &mut table;
+        for segment in parents {
+            let entry = current
+                .entry(segment.to_string())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            current = entry
+                .as_table_mut()
+                .ok_or_else(|| anyhow!("Can't set {key}: `{segment}` is not a table"))?;
+        }
+        current.insert(last.to_string(), value);
+        *self = toml::Value::Table(table)
+            .try_into()
+            .map_err(|err| anyhow!("Invalid value for {key}:\n{err}"))?;
+        Ok(())
+    }
+
+    /// Splits a dotted key into its segments, checking that it names a known
+    /// setting. Anything under `global_env` or a template's `env` is allowed.
+    fn check_key(key: &str) -> Result<Vec<&str>> {
+        let path: Vec<&str> = key.split('.').collect();
+        if path.iter().any(|segment| segment.is_empty()) {
+            return Err(anyhow!("Invalid setting `{key}`"));
+        }
+        let known: &[&str] = match path.as_slice() {
+            ["templates", _, setting, ..] if !Self::TEMPLATE_KEYS.contains(setting) => {
+                &Self::TEMPLATE_KEYS
+            }
+            ["profiles", _, setting, ..] if !Self::PROFILE_KEYS.contains(setting) => {
+                &Self::PROFILE_KEYS
+            }
+            [first, ..] if !Self::KEYS.contains(first) => &Self::KEYS,
+            ["global_env", name, ..]
+            | ["profiles", _, "global_env", name, ..]
+            | ["templates", _, "env", name, ..]
+                if is_reserved(&canonical_env_key(name)) =>
+            {
+                return Err(anyhow!(
+                    "`{name}` is reserved for hayaku's built-in variables"
+                ));
+            }
+            _ => return Ok(path),
+        };
+        Err(anyhow!(
+            "Unknown setting `{key}`. Settings are: {}",
+            known.join(", ")
+        ))
+    }
+
+    /// The settings with the profile `name` applied. Tables such as
+    /// `global_env` are merged key by key, and other values are replaced.
+    pub fn with_profile(&self, name: &str) -> Result<Self> {
+        let profile = self
+            .profiles
+            .get(name)
+            .ok_or_else(|| anyhow!("Profile '{name}' not found in settings"))?;
+        let mut table = self.to_table()?;
+        for (key, value) in &profile.settings {
+            if !Self::PROFILE_KEYS.contains(&key.as_str()) {
+                return Err(anyhow!("`{key}` can't be set in profile '{name}'"));
+            }
+            merge_value(&mut table, key, value);
+        }
+        toml::Value::Table(table)
+            .try_into()
+            .map_err(|err| anyhow!("Invalid settings in profile '{name}':\n{err}"))
+    }
+
+    /// The first profile with a `match` rule covering `dest_dir`. Rules that
+    /// aren't absolute are resolved like other settings paths, against `base`.
+    pub fn matching_profile(&self, base: &Path, dest_dir: &Path) -> Result<Option<String>> {
+        let dest_dir = std::path::absolute(dest_dir)?;
+        for (name, profile) in &self.profiles {
+            for rule in &profile.rules {
+                let pattern = resolve_settings_path(base, Path::new(&rule.path));
+                let glob = GlobBuilder::new(&pattern.to_string_lossy())
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|err| {
+                        anyhow!(
+                            "Invalid match path `{}` in profile '{name}':\n{err}",
+                            rule.path
+                        )
+                    })?;
+                if glob.compile_matcher().is_match(&dest_dir) {
+                    return Ok(Some(name.clone()));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Writes the settings to `path`. When the file already exists, only the
+    /// values that changed are replaced, so comments and formatting are kept.
+    pub fn write_to_file(&self, path: &Path) -> Result<()> {
+        // Ensure parent directory exists
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let toml_string = toml::to_string_pretty(self)
+            .map_err(|err| anyhow!("Failed to serialize settings to TOML:\n{err}"))?;
+        let mut document = if path.exists() {
+            std::fs::read_to_string(path)?
+                .parse::<DocumentMut>()
+                .map_err(|err| {
+                    anyhow!("Failed to parse settings file {}:\n{err}", path.display())
+                })?
+        } else {
+            DocumentMut::new()
+        };
+        let updated: DocumentMut = toml_string
+            .parse()
+            .map_err(|err| anyhow!("Failed to serialize settings to TOML:\n{err}"))?;
+        merge_table(document.as_table_mut(), updated.as_table());
+        std::fs::write(path, document.to_string())
+            .map_err(|err| anyhow!("Failed to write settings to {}:\n{err}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Sets `key` in `table` to `value`, merging tables recursively.
+fn merge_value(table: &mut toml::Table, key: &str, value: &toml::Value) {
+    match (table.get_mut(key), value) {
+        (Some(toml::Value::Table(existing)), toml::Value::Table(overrides)) => {
+            for (key, value) in overrides {
+                merge_value(existing, key, value);
+            }
+        }
+        _ => {
+            table.insert(key.to_string(), value.clone());
+        }
+    }
+}
+
+/// Makes `existing` hold the same values as `updated`, leaving items whose
+/// value didn't change untouched.
+fn merge_table(existing: &mut Table, updated: &Table) {
+    let removed: Vec<String> = existing
+        .iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| !updated.contains_key(key))
+        .collect();
+    for key in removed {
+        existing.remove(&key);
+    }
+    for (key, item) in updated.iter() {
+        match (existing.get_mut(key), item) {
+            (Some(Item::Table(existing)), Item::Table(updated)) => merge_table(existing, updated),
+            (Some(current), _) if same_value(current, item) => {}
+            (Some(Item::Value(current)), Item::Value(value)) => {
+                let decor = current.decor().clone();
+                *current = value.clone();
+                *current.decor_mut() = decor;
+            }
+            (Some(current), _) => *current = item.clone(),
+            (None, _) => {
+                existing.insert(key, item.clone());
+            }
+        }
+    }
+}
+
+/// Compares two items by value, ignoring formatting.
+fn same_value(a: &Item, b: &Item) -> bool {
+    let value = |item: &Item| {
+        let mut document = DocumentMut::new();
+        document.insert("value", item.clone());
+        toml::from_str::<toml::Table>(&document.to_string())
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+    };
+    value(a).is_some_and(|a| Some(a) == value(b))
+}
+
+/// The organization-wide settings file, which the user's settings override.
+pub const SYSTEM_SETTINGS_FILE: &str = "/etc/hayaku/settings.toml";
+/// Overrides the location of the system settings file.
+pub const SYSTEM_SETTINGS_ENV_VAR: &str = "HAYAKU_SYSTEM_SETTINGS";
+/// A repository's settings, found in the project directory or one of its
+/// parents. These override the user's settings.
+pub const PROJECT_SETTINGS_FILE: &str = ".hayaku/settings.toml";
+
+/// Where a layer of settings comes from, in order of precedence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SettingsSource {
+    System,
+    User,
+    Project,
+}
+
+#[derive(Debug, Clone)]
+pub struct SettingsLayer {
+    pub source: SettingsSource,
+    pub path: PathBuf,
+    table: toml::Table,
+}
+
+/// The settings files that apply to a directory, lowest precedence first.
+#[derive(Debug, Clone, Default)]
+pub struct LayeredSettings {
+    layers: Vec<SettingsLayer>,
+}
+
+impl LayeredSettings {
+    /// Loads the system and user settings files, and the nearest project
+    /// settings file above `project_dir`. Files that don't exist are skipped.
+    pub fn load(system_path: Option<&Path>, user_path: &Path, project_dir: &Path) -> Result<Self> {
+        let project_path = find_project_settings(project_dir);
+        let mut layers = Vec::new();
+        for (source, path) in [
+            (SettingsSource::System, system_path.map(Path::to_path_buf)),
+            (SettingsSource::User, Some(user_path.to_path_buf())),
+            (SettingsSource::Project, project_path),
+        ] {
+            let Some(path) = path.filter(|path| path.is_file()) else {
+                continue;
+            };
+            let raw = std::fs::read_to_string(&path).map_err(|err| {
+                anyhow!("Failed to read settings file {}:\n{err}", path.display())
+            })?;
+            let mut table: toml::Table = toml::from_str(&raw).map_err(|err| {
+                anyhow!("Failed to parse settings file {}:\n{err}", path.display())
+            })?;
+            // The user's paths are resolved against the hayaku directory
+            // later; other files' are relative to the file
+            if source != SettingsSource::User
+                && let Some(dir) = path.parent()
+            {
+                absolutize_paths(&mut table, dir);
+            }
+            layers.push(SettingsLayer {
+                source,
+                path,
+                table,
+            });
+        }
+        Ok(Self { layers })
+    }
+
+    pub fn layers(&self) -> &[SettingsLayer] {
+        &self.layers
+    }
+
+    /// The layers merged into one set of settings. Tables are merged key by
+    /// key, and other values from later layers replace earlier ones.
+    pub fn settings(&self) -> Result<HayakuSettings> {
+        let mut merged = toml::Table::new();
+        for layer in &self.layers {
+            for (key, value) in &layer.table {
+                merge_value(&mut merged, key, value);
+            }
+        }
+        toml::Value::Table(merged).try_into().map_err(|err| {
+            let paths: Vec<String> = self
+                .layers
+                .iter()
+                .map(|layer| layer.path.display().to_string())
+                .collect();
+            anyhow!("Invalid settings in {}:\n{err}", paths.join(", "))
+        })
+    }
+
+    /// The layer that a dotted key's value comes from.
+    pub fn origin(&self, key: &str) -> Option<&SettingsLayer> {
+        self.layers.iter().rev().find(|layer| {
+            let mut value = Some(&layer.table);
+            let mut segments = key.split('.').peekable();
+            while let Some(segment) = segments.next() {
+                match value.and_then(|table| table.get(segment)) {
+                    Some(toml::Value::Table(table)) => value = Some(table),
+                    Some(_) => return segments.peek().is_none(),
+                    None => return false,
+                }
+            }
+            false
+        })
+    }
+}
+
+/// The nearest `.hayaku/settings.toml` in `dir` or one of its parents.
+pub fn find_project_settings(dir: &Path) -> Option<PathBuf> {
+    let dir = std::path::absolute(dir).ok()?;
+    dir.ancestors()
+        .map(|ancestor| ancestor.join(PROJECT_SETTINGS_FILE))
+        .find(|path| path.is_file())
+}
+
+/// Makes the relative paths in `projects_dir` and `template_paths` relative
+/// to `dir`.
+fn absolutize_paths(table: &mut toml::Table, dir: &Path) {
+    let absolutize = |value: &mut toml::Value| {
+        if let toml::Value::String(path) = value
+            && !path.starts_with('~')
+            && Path::new(path.as_str()).is_relative()
+        {
+            *path = dir.join(&*path).display().to_string();
+        }
+    };
+    if let Some(value) = table.get_mut("projects_dir") {
+        absolutize(value);
+    }
+    if let Some(toml::Value::Array(paths)) = table.get_mut("template_paths") {
+        for entry in paths {
+            match entry {
+                toml::Value::Table(named) => {
+                    if let Some(path) = named.get_mut("path") {
+                        absolutize(path);
+                    }
+                }
+                path => absolutize(path),
+            }
+        }
+    }
+}
+
+/// Sets the value at a dotted path in the settings file at `path`, creating
+/// it if needed. Only that value is checked, so that a broken value elsewhere
+/// in the file can still be fixed with `config set`, and the rest of the file
+/// is kept as it is.
+pub fn set_in_file(path: &Path, key: &str, value: toml::Value) -> Result<()> {
+    HayakuSettings::default().set(key, value.clone())?;
+    let path_segments: Vec<&str> = key.split('.').collect();
+    let (last, parents) = path_segments
+        .split_last()
+        .expect("split returns at least one item");
+
+    let mut document = read_document(path)?;
+    let mut current: &mut dyn TableLike = document.as_table_mut();
+    for segment in parents {
+        if current.get(segment).is_none() {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            current.insert(segment, Item::Table(table));
+        }
+        current = current
+            .get_mut(segment)
+            .and_then(Item::as_table_like_mut)
+            .ok_or_else(|| anyhow!("Can't set {key}: `{segment}` is not a table"))?;
+    }
+    let mut wrapper = toml::Table::new();
+    wrapper.insert("value".to_string(), value);
+    let serialized = toml::to_string(&wrapper)
+        .map_err(|err| anyhow!("Failed to serialize {key} to TOML:\n{err}"))?;
+    let mut parsed: DocumentMut = serialized
+        .parse()
+        .map_err(|err| anyhow!("Failed to serialize {key} to TOML:\n{err}"))?;
+    let mut item = parsed
+        .remove("value")
+        .expect("the value was just serialized");
+    // Keep a comment after the value being replaced
+    if let (Some(Item::Value(old)), Item::Value(new)) = (current.get(last), &mut item) {
+        *new.decor_mut() = old.decor().clone();
+    }
+    current.insert(last, item);
+    write_document(path, &document)
+}
+
+/// Removes the value at a dotted path from the settings file at `path`,
+/// without reading the rest of the file as settings. Returns whether it was
+/// set.
+pub fn unset_in_file(path: &Path, key: &str) -> Result<bool> {
+    if !path.exists() {
+        return Ok(false);
+    }
+    let path_segments: Vec<&str> = key.split('.').collect();
+    let (last, parents) = path_segments
+        .split_last()
+        .expect("split returns at least one item");
+
+    let mut document = read_document(path)?;
+    let mut current: &mut dyn TableLike = document.as_table_mut();
+    for segment in parents {
+        match current.get_mut(segment).and_then(Item::as_table_like_mut) {
+            Some(next) => current = next,
+            None => return Ok(false),
+        }
+    }
+    if current.remove(last).is_none() {
+        return Ok(false);
+    }
+    write_document(path, &document)?;
+    Ok(true)
+}
+
+/// The `editor` setting from the settings file at `path`, if it is a string.
+/// The rest of the file isn't checked, so the editor can open a broken file.
+pub fn editor_in_file(path: &Path) -> Result<Option<String>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(read_document(path)?
+        .get("editor")
+        .and_then(Item::as_str)
+        .map(str::to_string))
+}
+
+fn read_document(path: &Path) -> Result<DocumentMut> {
+    if !path.exists() {
+        return Ok(DocumentMut::new());
+    }
+    std::fs::read_to_string(path)?
+        .parse()
+        .map_err(|err| anyhow!("Failed to parse settings file {}:\n{err}", path.display()))
+}
+
+fn write_document(path: &Path, document: &DocumentMut) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, document.to_string())
+        .map_err(|err| anyhow!("Failed to write settings to {}:\n{err}", path.display()))
+}
+
+/// Parses a value given on the command line as TOML, e.g. `true`, `8080` or
+/// `["a", "b"]`. Anything that isn't valid TOML is taken as a string.
+pub fn parse_setting_value(text: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {text}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(text.to_string()))
+}
+
+/// Replaces the paths in `projects_dir`, including profiles' `projects_dir`,
+/// and `template_paths` of a settings file, keeping its comments and
+/// formatting. `rewrite` returns the new path, or `None` to keep a path.
+pub fn rewrite_settings_paths(
+    contents: &str,
+    rewrite: impl Fn(&str) -> Option<String>,
+) -> Result<String> {
+    let mut document: DocumentMut = contents
+        .parse()
+        .map_err(|err| anyhow!("Failed to parse settings:\n{err}"))?;
+    let rewrite_value = |value: &mut toml_edit::Value| {
+        if let Some(path) = value.as_str().and_then(&rewrite) {
+            let decor = value.decor().clone();
+            *value = path.into();
+            *value.decor_mut() = decor;
+        }
+    };
+    let rewrite_projects_dir = |table: &mut Table| {
+        if let Some(value) = table.get_mut("projects_dir").and_then(Item::as_value_mut) {
+            rewrite_value(value);
+        }
+    };
+    rewrite_projects_dir(document.as_table_mut());
+    if let Some(profiles) = document.get_mut("profiles").and_then(Item::as_table_mut) {
+        for (_, profile) in profiles.iter_mut() {
+            if let Some(profile) = profile.as_table_mut() {
+                rewrite_projects_dir(profile);
+            }
+        }
+    }
+    match document.get_mut("template_paths") {
+        Some(Item::Value(toml_edit::Value::Array(paths))) => {
+            for entry in paths.iter_mut() {
+                match entry {
+                    toml_edit::Value::InlineTable(named) => {
+                        if let Some(path) = named.get_mut("path") {
+                            rewrite_value(path);
+                        }
+                    }
+                    path => rewrite_value(path),
+                }
+            }
+        }
+        Some(Item::ArrayOfTables(entries)) => {
+            for entry in entries.iter_mut() {
+                if let Some(path) = entry.get_mut("path").and_then(Item::as_value_mut) {
+                    rewrite_value(path);
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(document.to_string())
+}
+
+/// Expands a leading `~` and resolves relative paths against `base`.
+pub fn resolve_settings_path(base: &Path, path: &Path) -> PathBuf {
+    if let Ok(rest) = path.strip_prefix("~")
+        && let Some(home) = std::env::home_dir()
+    {
+        return home.join(rest);
+    }
+    base.join(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_settings(path: &Path) -> Result<HayakuSettings> {
+        Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    #[test]
+    fn editor_setting_supports_arguments() {
+        let settings = HayakuSettings {
+            editor: Some("nvim -p \"--cmd=set nu\"".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            settings.editor_command().unwrap(),
+            vec!["nvim", "-p", "--cmd=set nu"]
+        );
+
+        let settings = HayakuSettings {
+            editor: Some("  ".to_string()),
+            ..Default::default()
+        };
+        assert!(settings.editor_command().is_err());
+    }
+
+    #[test]
+    fn parses_per_template_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hayaku.settings.toml");
+        std::fs::write(
+            &path,
+            "default_template = \"rust\"\nprojects_dir = \"~/code\"\n\n[templates.rust.env]\ncrate_type = \"lib\"\n",
+        )
+        .unwrap();
+
+        let settings = read_settings(&path).unwrap();
+
+        assert_eq!(settings.default_template.as_deref(), Some("rust"));
+        assert_eq!(settings.projects_dir, Some(PathBuf::from("~/code")));
+        assert_eq!(
+            settings.template_env("rust").unwrap()["crate_type"].as_str(),
+            Some("lib")
+        );
+        assert!(settings.template_env("web").is_none());
+    }
+
+    #[test]
+    fn gets_and_sets_settings_by_dotted_key() {
+        let mut settings: HayakuSettings =
+            toml::from_str("editor = \"vim\"\n\n[global_env]\nlicense = \"MIT\"\n").unwrap();
+
+        settings
+            .set("global_env.company.name", parse_setting_value("Acme"))
+            .unwrap();
+        settings
+            .set("templates.rust.env.crate_type", parse_setting_value("lib"))
+            .unwrap();
+        settings
+            .set("template_paths", parse_setting_value("[\"~/t\"]"))
+            .unwrap();
+
+        assert_eq!(
+            settings.get("global_env.company.name").unwrap(),
+            Some(toml::Value::from("Acme"))
+        );
+        assert_eq!(
+            settings.template_env("rust").unwrap()["crate_type"].as_str(),
+            Some("lib")
+        );
+        assert_eq!(
+            settings.template_paths,
+            vec![TemplatePath::Path(PathBuf::from("~/t"))]
+        );
+        assert!(
+            settings
+                .entries()
+                .unwrap()
+                .contains(&("global_env.license".to_string(), toml::Value::from("MIT")))
+        );
+
+        assert!(settings.set("edtor", parse_setting_value("vim")).is_err());
+        assert!(
+            settings
+                .set("global_env.hayaku", parse_setting_value("x"))
+                .is_err()
+        );
+        assert!(
+            settings
+                .set("templates.rust.foo", parse_setting_value("1"))
+                .is_err()
+        );
+        assert!(
+            settings
+                .set("templates.rust.hooks", parse_setting_value("false"))
+                .is_err()
+        );
+        assert!(settings.get("editor.path").unwrap().is_none());
+        assert!(settings.set("editor", parse_setting_value("5")).is_err());
+        assert_eq!(settings.editor.as_deref(), Some("vim"));
+    }
+
+    #[test]
+    fn fixes_broken_settings_files_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hayaku.settings.toml");
+        std::fs::write(
+            &path,
+            "editor = 5\n\n# My settings\n[global_env]\nlicense = \"MIT\" # default\n",
+        )
+        .unwrap();
+        assert!(read_settings(&path).is_err());
+
+        set_in_file(
+            &path,
+            "global_env.license",
+            parse_setting_value("Apache-2.0"),
+        )
+        .unwrap();
+        set_in_file(
+            &path,
+            "templates.rust.env.crate_type",
+            parse_setting_value("lib"),
+        )
+        .unwrap();
+        assert!(set_in_file(&path, "default_template", parse_setting_value("5")).is_err());
+        assert!(unset_in_file(&path, "editor").unwrap());
+        assert!(!unset_in_file(&path, "editor").unwrap());
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            written,
+            "\n# My settings\n[global_env]\nlicense = \"Apache-2.0\" # default\n\n[templates.rust.env]\ncrate_type = \"lib\"\n"
+        );
+        let settings = read_settings(&path).unwrap();
+        assert_eq!(settings.editor, None);
+        assert_eq!(
+            settings.template_env("rust").unwrap()["crate_type"].as_str(),
+            Some("lib")
+        );
+    }
+
+    #[test]
+    fn profiles_override_settings() {
+        let settings: HayakuSettings = toml::from_str(
+            r#"
+            default_template = "rust"
+
+            [global_env]
+            author = "Ada"
+            license = "MIT"
+
+            [profiles.work]
+            default_template = "service"
+
+            [profiles.work.global_env]
+            author = "Ada (Acme)"
+
+            [[profiles.work.match]]
+            path = "/src/work/**"
+
+            [profiles.broken]
+            template_paths = ["~/elsewhere"]
+            "#,
+        )
+        .unwrap();
+
+        let work = settings.with_profile("work").unwrap();
+        assert_eq!(work.default_template.as_deref(), Some("service"));
+        let global_env = work.global_env.unwrap();
+        assert_eq!(global_env["author"].as_str(), Some("Ada (Acme)"));
+        assert_eq!(global_env["license"].as_str(), Some("MIT"));
+
+        assert!(settings.with_profile("broken").is_err());
+        assert!(settings.with_profile("missing").is_err());
+
+        let base = Path::new("/home/ada/.hayaku");
+        assert_eq!(
+            settings
+                .matching_profile(base, Path::new("/src/work/api"))
+                .unwrap()
+                .as_deref(),
+            Some("work")
+        );
+        assert_eq!(
+            settings
+                .matching_profile(base, Path::new("/src/home/api"))
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn merges_system_user_and_project_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let system = dir.path().join("system.toml");
+        let user = dir.path().join("hayaku.settings.toml");
+        let project = dir.path().join("repo");
+        std::fs::create_dir_all(project.join(".hayaku")).unwrap();
+        std::fs::create_dir_all(project.join("crates/api")).unwrap();
+        std::fs::write(
+            &system,
+            "editor = \"vim\"\n\n[global_env]\ncompany = \"Acme\"\nlicense = \"Proprietary\"\n",
+        )
+        .unwrap();
+        std::fs::write(&user, "[global_env]\nlicense = \"MIT\"\n").unwrap();
+        std::fs::write(
+            project.join(PROJECT_SETTINGS_FILE),
+            "editor = \"code\"\ntemplate_paths = [\"templates\"]\n",
+        )
+        .unwrap();
+
+        let layers =
+            LayeredSettings::load(Some(&system), &user, &project.join("crates/api")).unwrap();
+        let settings = layers.settings().unwrap();
+
+        assert_eq!(settings.editor.as_deref(), Some("code"));
+        let global_env = settings.global_env.unwrap();
+        assert_eq!(global_env["company"].as_str(), Some("Acme"));
+        assert_eq!(global_env["license"].as_str(), Some("MIT"));
+        assert_eq!(
+            settings.template_paths,
+            vec![TemplatePath::Path(project.join(".hayaku/templates"))]
+        );
+        let origin = |key| layers.origin(key).map(|layer| layer.source);
+        assert_eq!(origin("editor"), Some(SettingsSource::Project));
+        assert_eq!(origin("global_env.company"), Some(SettingsSource::System));
+        assert_eq!(origin("global_env.license"), Some(SettingsSource::User));
+        assert_eq!(origin("default_template"), None);
+    }
+
+    #[test]
+    fn writing_keeps_comments_and_unknown_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hayaku.settings.toml");
+        let original = "# My settings\neditor = \"vim\" # for now\nfuture_option = [1, 2]\n\n[global_env]\n# used by every template\nLICENSE = \"MIT\"\n\n[templates.rust]\nsomething_new = true\n";
+        std::fs::write(&path, original).unwrap();
+
+        let mut settings = read_settings(&path).unwrap();
+        settings.write_to_file(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), original);
+
+        settings.editor = Some("nvim".to_string());
+        settings.default_template = Some("rust".to_string());
+        settings.write_to_file(&path).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+
+        assert!(
+            written.starts_with("# My settings\neditor = \"nvim\""),
+            "{written}"
+        );
+        assert!(written.contains("future_option = [1, 2]"), "{written}");
+        assert!(
+            written.contains("# used by every template\nLICENSE = \"MIT\""),
+            "{written}"
+        );
+        assert!(written.contains("something_new = true"), "{written}");
+        assert!(written.contains("default_template = \"rust\""), "{written}");
+        let reread = read_settings(&path).unwrap();
+        assert_eq!(reread.editor.as_deref(), Some("nvim"));
+    }
+}
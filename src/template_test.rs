@@ -0,0 +1,282 @@
+use crate::config::TemplateConfig;
+use crate::env::{self, Answers, HostInfo};
+use crate::settings::HayakuSettings;
+use crate::templating;
+use anyhow::{Context, Result, anyhow};
+use ignore::WalkBuilder;
+use serde::Deserialize;
+use similar::TextDiff;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Where a template keeps its fixtures, away from any `tests` directory that
+/// belongs in generated projects.
+pub const FIXTURE_DIR: &str = ".hayaku/tests";
+const SNAPSHOT_DIR: &str = "snapshots";
+
+/// An answer set in a template's `.hayaku/tests/<name>.toml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fixture {
+    #[serde(skip)]
+    pub name: String,
+    /// Defaults to the fixture name
+    pub project_name: Option<String>,
+    /// A shell command run in the rendered project, e.g. `cargo check`
+    pub check: Option<String>,
+    #[serde(default)]
+    pub answers: toml::Table,
+    /// Stands in for the user's global settings so fixtures are reproducible
+    #[serde(default)]
+    pub global_env: HashMap<String, toml::Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileDiff {
+    Added(PathBuf),
+    Removed(PathBuf),
+    Changed(PathBuf, String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FixtureResult {
+    pub name: String,
+    pub missing_snapshot: bool,
+    pub diffs: Vec<FileDiff>,
+    pub updated: bool,
+    /// Output of a failed `check` command
+    pub check_failure: Option<String>,
+}
+
+impl FixtureResult {
+    pub fn passed(&self) -> bool {
+        (self.updated || (!self.missing_snapshot && self.diffs.is_empty()))
+            && self.check_failure.is_none()
+    }
+}
+
+pub fn discover_fixtures(template_dir: &Path) -> Result<Vec<Fixture>> {
+    let fixture_dir = template_dir.join(FIXTURE_DIR);
+    let mut fixtures = Vec::new();
+    if !fixture_dir.is_dir() {
+        return Ok(fixtures);
+    }
+
+    for entry in fs::read_dir(&fixture_dir)? {
+        let path = entry?.path();
+        if !path.is_file() || path.extension().is_none_or(|ext| ext != "toml") {
+            continue;
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read fixture {}", path.display()))?;
+        let mut fixture: Fixture = toml::from_str(&content)
+            .map_err(|err| anyhow!("Failed to parse fixture {}:\n{err}", path.display()))?;
+        fixture.name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| anyhow!("Invalid fixture file name {}", path.display()))?
+            .to_string();
+        fixtures.push(fixture);
+    }
+    fixtures.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(fixtures)
+}
+
+fn snapshot_dir(template_dir: &Path, fixture: &Fixture) -> PathBuf {
+    template_dir
+        .join(FIXTURE_DIR)
+        .join(SNAPSHOT_DIR)
+        .join(&fixture.name)
+}
+
+fn read_snapshot(dir: &Path) -> Result<BTreeMap<PathBuf, String>> {
+    let mut files = BTreeMap::new();
+    for entry in WalkBuilder::new(dir).standard_filters(false).build() {
+        let entry = entry?;
+        if !entry.file_type().map(|ft| ft.is_file()).unwrap_or(false) {
+            continue;
+        }
+        let contents = fs::read_to_string(entry.path())
+            .with_context(|| format!("Failed to read snapshot {}", entry.path().display()))?;
+        files.insert(entry.path().strip_prefix(dir)?.to_path_buf(), contents);
+    }
+    Ok(files)
+}
+
+fn diff_files(
+    expected: &BTreeMap<PathBuf, String>,
+    actual: &BTreeMap<PathBuf, String>,
+) -> Vec<FileDiff> {
+    let mut diffs = Vec::new();
+    for (path, expected_contents) in expected {
+        match actual.get(path) {
+            None => diffs.push(FileDiff::Removed(path.clone())),
+            Some(actual_contents) if actual_contents != expected_contents => {
+                let name = path.display().to_string();
+                let diff = TextDiff::from_lines(expected_contents, actual_contents)
+                    .unified_diff()
+                    .header(&format!("snapshot/{name}"), &format!("rendered/{name}"))
+                    .to_string();
+                diffs.push(FileDiff::Changed(path.clone(), diff));
+            }
+            Some(_) => {}
+        }
+    }
+    for path in actual.keys() {
+        if !expected.contains_key(path) {
+            diffs.push(FileDiff::Added(path.clone()));
+        }
+    }
+    diffs
+}
+
+/// Renders a fixture into a temporary directory and compares the result
+/// against its snapshot. With `update`, the snapshot is replaced instead.
+pub fn run_fixture(
+    template_dir: &Path,
+    config: &TemplateConfig,
+    fixture: &Fixture,
+    update: bool,
+) -> Result<FixtureResult> {
+    let settings = HayakuSettings {
+        global_env: Some(fixture.global_env.clone()),
+        ..Default::default()
+    };
+    let project_name = fixture.project_name.as_deref().unwrap_or(&fixture.name);
+    let context = env::build_context(
+        project_name,
+        config,
+        &settings,
+        &Answers::fixed(fixture.answers.clone()),
+        // Fixtures don't depend on the machine they run on
+        &HostInfo::default(),
+    )
+    .with_context(|| format!("Failed to build context for fixture {}", fixture.name))?;
+
+    let rendered = templating::render_project(template_dir, &context)?;
+    let mut result = FixtureResult {
+        name: fixture.name.clone(),
+        ..Default::default()
+    };
+
+    let snapshot_dir = snapshot_dir(template_dir, fixture);
+    if update {
+        if snapshot_dir.exists() {
+            fs::remove_dir_all(&snapshot_dir)?;
+        }
+        for (rel_path, contents) in &rendered {
+            templating::write_file(&snapshot_dir.join(rel_path), contents)?;
+        }
+        result.updated = true;
+    } else if snapshot_dir.is_dir() {
+        result.diffs = diff_files(&read_snapshot(&snapshot_dir)?, &rendered);
+    } else {
+        result.missing_snapshot = true;
+    }
+
+    if let Some(check) = &fixture.check {
+        let output_dir = tempfile::tempdir()?;
+        let project_dir = output_dir.path().join(project_name);
+        for (rel_path, contents) in &rendered {
+            templating::write_file(&project_dir.join(rel_path), contents)?;
+        }
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(check)
+            .current_dir(&project_dir)
+            .output()
+            .with_context(|| format!("Failed to run check `{check}`"))?;
+        if !output.status.success() {
+            result.check_failure = Some(format!(
+                "`{check}` exited with {}\n{}{}",
+                output.status,
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, rel: &str, contents: &str) {
+        let path = dir.join(rel);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn template() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "hayaku.toml",
+            "[template]\nname = \"t\"\n\n[env.greeting]\ntype = \"string\"\nprompt = \"?\"\n",
+        );
+        write(
+            dir.path(),
+            "hello.txt",
+            "{{ GREETING }} {{ PROJECT_NAME }}\n",
+        );
+        write(
+            dir.path(),
+            ".hayaku/tests/basic.toml",
+            "check = \"test -f hello.txt\"\n\n[answers]\ngreeting = \"hi\"\n",
+        );
+        dir
+    }
+
+    #[test]
+    fn updates_then_matches_snapshots() {
+        let dir = template();
+        let config = TemplateConfig::try_from_dir(dir.path()).unwrap();
+        let fixtures = discover_fixtures(dir.path()).unwrap();
+        assert_eq!(fixtures.len(), 1);
+
+        let missing = run_fixture(dir.path(), &config, &fixtures[0], false).unwrap();
+        assert!(missing.missing_snapshot);
+        assert!(!missing.passed());
+
+        let updated = run_fixture(dir.path(), &config, &fixtures[0], true).unwrap();
+        assert!(updated.passed());
+        assert_eq!(
+            fs::read_to_string(dir.path().join(".hayaku/tests/snapshots/basic/hello.txt")).unwrap(),
+            "hi basic\n"
+        );
+
+        let matched = run_fixture(dir.path(), &config, &fixtures[0], false).unwrap();
+        assert!(matched.passed(), "{matched:?}");
+    }
+
+    #[test]
+    fn reports_diffs_and_failed_checks() {
+        let dir = template();
+        write(
+            dir.path(),
+            ".hayaku/tests/snapshots/basic/hello.txt",
+            "hello basic\n",
+        );
+        write(dir.path(), ".hayaku/tests/snapshots/basic/old.txt", "");
+        write(
+            dir.path(),
+            ".hayaku/tests/basic.toml",
+            "check = \"exit 3\"\n\n[answers]\ngreeting = \"hi\"\n",
+        );
+        let config = TemplateConfig::try_from_dir(dir.path()).unwrap();
+        let fixtures = discover_fixtures(dir.path()).unwrap();
+
+        let result = run_fixture(dir.path(), &config, &fixtures[0], false).unwrap();
+
+        assert!(!result.passed());
+        assert_eq!(result.diffs.len(), 2);
+        assert!(matches!(&result.diffs[0], FileDiff::Changed(path, diff)
+            if path == Path::new("hello.txt") && diff.contains("-hello basic") && diff.contains("+hi basic")));
+        assert_eq!(result.diffs[1], FileDiff::Removed(PathBuf::from("old.txt")));
+        assert!(result.check_failure.is_some());
+    }
+}
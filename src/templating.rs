@@ -1,13 +1,53 @@
+use crate::config::TemplateConfig;
+use crate::env::canonical_env_key;
+use crate::template_test;
 use anyhow::{Context as AnyhowContext, Result};
 use ignore::WalkBuilder;
 use ignore::overrides::OverrideBuilder;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use tera::{Context as TeraContext, Tera};
 
-pub fn create_project(template_dir: &Path, dest_dir: &Path, context: &TeraContext) -> Result<()> {
+/// Renders every file in a template, keyed by its path relative to the new
+/// project.
+pub fn render_project(
+    template_dir: &Path,
+    context: &TeraContext,
+) -> Result<BTreeMap<PathBuf, String>> {
     let mut tera = Tera::default();
+    let mut rendered = BTreeMap::new();
+
+    for entry in template_walker(template_dir)?.build() {
+        let entry = entry?;
+        if !entry.file_type().map(|ft| ft.is_file()).unwrap_or(false) {
+            continue;
+        }
+        let rel_path = entry.path().strip_prefix(template_dir)?;
+        let contents = render_template_file(entry.path(), &mut tera, context)?;
+        rendered.insert(dest_rel_path(rel_path, context), contents);
+    }
+    Ok(rendered)
+}
 
+/// The paths a template renders to, relative to the new project, with `[VAR]`
+/// segments left in place.
+pub fn output_paths(template_dir: &Path) -> Result<Vec<PathBuf>> {
+    let context = TeraContext::new();
+    let mut paths = Vec::new();
+    for entry in template_walker(template_dir)?.build() {
+        let entry = entry?;
+        if !entry.file_type().map(|ft| ft.is_file()).unwrap_or(false) {
+            continue;
+        }
+        let rel_path = entry.path().strip_prefix(template_dir)?;
+        paths.push(dest_rel_path(rel_path, &context));
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+pub fn create_project(template_dir: &Path, dest_dir: &Path, context: &TeraContext) -> Result<()> {
     if !dest_dir.exists() {
         fs::create_dir_all(dest_dir).with_context(|| {
             format!(
@@ -17,53 +57,97 @@ pub fn create_project(template_dir: &Path, dest_dir: &Path, context: &TeraContex
         })?;
     }
 
-    let mut overrides = OverrideBuilder::new(".");
+    for (rel_path, contents) in render_project(template_dir, context)? {
+        write_file(&dest_dir.join(rel_path), &contents)?;
+    }
+    Ok(())
+}
+
+pub fn write_file(dest_path: &Path, contents: &str) -> Result<()> {
+    if let Some(parent) = dest_path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create parent directory {}", parent.display()))?;
+    }
+    fs::write(dest_path, contents)
+        .with_context(|| format!("Failed to write rendered file {}", dest_path.display()))?;
+    Ok(())
+}
+
+/// Walks the files of a template that should be rendered into a project.
+pub fn template_walker(template_dir: &Path) -> Result<WalkBuilder> {
+    let mut overrides = OverrideBuilder::new(template_dir);
     overrides.add("!**/.git")?;
     overrides.add("!**/hayaku.toml")?;
+    overrides.add(&format!("!/{}/", template_test::FIXTURE_DIR))?;
     let overrides = overrides.build()?;
 
     let mut walker = WalkBuilder::new(template_dir);
     walker.git_ignore(true).hidden(false).overrides(overrides);
+    Ok(walker)
+}
 
-    log_context_variables(context)?;
-
-    for entry in walker.build() {
-        let entry = entry?;
-        if !entry.file_type().map(|ft| ft.is_file()).unwrap_or(false) {
-            continue;
+/// Escapes every Tera delimiter in `text`, so that it renders as is.
+pub fn escape_tera(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        escaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match ["{{", "{%", "{#"]
+            .into_iter()
+            .find(|tag| rest.starts_with(tag))
+        {
+            Some(tag) => {
+                escaped.push_str(&format!("{{{{ \"{tag}\" }}}}"));
+                rest = &rest[tag.len()..];
+            }
+            None => {
+                escaped.push('{');
+                rest = &rest[1..];
+            }
         }
-        let rel_path = entry.path().strip_prefix(template_dir)?;
-        let dest_rel: PathBuf = rel_path.to_path_buf();
-        let dest_path = dest_dir.join(&dest_rel);
-
-        render_from_template_file(entry.path(), &dest_path, &mut tera, context)?;
     }
-    Ok(())
+    escaped.push_str(rest);
+    escaped
 }
 
 fn process_dest_path(dest_path: &Path, context: &TeraContext) -> PathBuf {
-    let components = dest_path.components().map(|comp| {
-        let comp_str = comp.as_os_str().to_string_lossy();
-        if comp_str.starts_with('[') && comp_str.ends_with(']') {
-            let var_name = &comp_str[1..comp_str.len() - 1];
-            if let Some(value) = context.get(var_name) {
-                if let Some(s) = value.as_str() {
-                    return PathBuf::from(s);
-                }
-            }
+    dest_path
+        .components()
+        .map(|comp| {
+            PathBuf::from(substitute_path_variables(
+                &comp.as_os_str().to_string_lossy(),
+                context,
+            ))
+        })
+        .collect()
+}
+
+/// Replaces every `[VAR]` in a path component with the matching string value
+/// from the context. Unknown variables are left untouched.
+fn substitute_path_variables(component: &str, context: &TeraContext) -> String {
+    let mut result = String::new();
+    let mut rest = component;
+    while let Some(start) = rest.find('[') {
+        let Some(len) = rest[start..].find(']') else {
+            break;
+        };
+        let var_name = &rest[start + 1..start + len];
+        result.push_str(&rest[..start]);
+        match context.get(var_name).and_then(|value| value.as_str()) {
+            Some(value) => result.push_str(value),
+            None => result.push_str(&rest[start..=start + len]),
         }
-        PathBuf::from(comp.as_os_str())
-    });
-    components.collect::<PathBuf>()
+        rest = &rest[start + len + 1..];
+    }
+    result.push_str(rest);
+    result
 }
 
-fn render_from_template_file(
-    template_file: &Path,
-    dest_path: &Path,
-    tera: &mut Tera,
-    context: &TeraContext,
-) -> Result<()> {
-    let mut dest_path = process_dest_path(dest_path, context);
+/// The path a template file is rendered to, with `[VAR]` segments
+/// substituted and any `.tera` extension removed.
+fn dest_rel_path(rel_path: &Path, context: &TeraContext) -> PathBuf {
+    let mut dest_path = process_dest_path(rel_path, context);
 
     if dest_path
         .extension()
@@ -73,38 +157,55 @@ fn render_from_template_file(
     {
         dest_path.set_extension("");
     }
+    dest_path
+}
 
-    if let Some(parent) = dest_path.parent() {
-        fs::create_dir_all(parent)
-            .with_context(|| format!("Failed to create parent directory {}", parent.display()))?;
-    }
-    let contents = fs::read_to_string(template_file)?;
-    let rendered = tera.render_str(&contents, &context).map_err(|e| {
+fn render_template_file(
+    template_file: &Path,
+    tera: &mut Tera,
+    context: &TeraContext,
+) -> Result<String> {
+    let contents = fs::read_to_string(template_file)
+        .with_context(|| format!("Failed to read {}", template_file.display()))?;
+    tera.render_str(&contents, context).map_err(|e| {
         anyhow::anyhow!(
             "Failed to render template file {}:\n{:?}",
             template_file.display(),
             e
         )
-    })?;
-
-    fs::write(&dest_path, rendered)
-        .with_context(|| format!("Failed to write rendered file {}", dest_path.display()))?;
-    Ok(())
+    })
 }
 
-fn log_context_variables(context: &TeraContext) -> Result<()> {
-    let context_json = context.clone().into_json();
+/// Logs the context, with the values of `config`'s secret variables masked.
+pub fn log_context_variables(context: &TeraContext, config: &TemplateConfig) -> Result<()> {
+    let context_json = masked_context(context, config);
     let pretty = serde_json::to_string_pretty(&context_json)
         .map_err(|err| anyhow::anyhow!("Failed to format context for logging: {err}"))?;
     cliclack::log::info(format!("Creating project with context:\n{}", pretty))?;
     Ok(())
 }
 
+fn masked_context(context: &TeraContext, config: &TemplateConfig) -> serde_json::Value {
+    let mut context_json = context.clone().into_json();
+    if let Some(values) = context_json.as_object_mut() {
+        for (raw_key, env_cfg) in config.env.iter() {
+            if let Some(value) = values.get_mut(&canonical_env_key(raw_key))
+                && env_cfg.is_secret()
+            {
+                *value = serde_json::Value::from("********");
+            }
+        }
+    }
+    context_json
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::Hayaku;
-    use std::{collections::HashMap, fs, path::Path};
+    use crate::env::{Answers, HostInfo};
+    use crate::settings::HayakuSettings;
+    use indexmap::IndexMap;
+    use std::{fs, path::Path};
 
     use crate::config::TemplateConfig;
     use crate::env;
@@ -115,7 +216,13 @@ mod tests {
             display_name: None,
             description: None,
             author: None,
-            env: HashMap::new(),
+            tags: Vec::new(),
+            version: None,
+            min_hayaku_version: None,
+            homepage: None,
+            answers_file: true,
+            project_name: None,
+            env: IndexMap::new(),
         }
     }
 
@@ -131,7 +238,6 @@ mod tests {
     fn renders_template_variables() {
         let template_dir = tempfile::tempdir().unwrap();
         let dest_dir = tempfile::tempdir().unwrap().path().join("demo");
-        let hayaku = Hayaku::try_new_from_dir(tempfile::tempdir().unwrap().path()).unwrap();
 
         write_template(template_dir.path(), "file.txt", b"Hello {{ project_name }}");
         write_template(
@@ -139,7 +245,14 @@ mod tests {
             "nested/config.toml",
             b"name = \"{{ PROJECT_NAME }}\"",
         );
-        let context = env::build_context("demo", &config("some_template"), &hayaku).unwrap();
+        let context = env::build_context(
+            "demo",
+            &config("some_template"),
+            &HayakuSettings::default(),
+            &Answers::interactive(),
+            &HostInfo::default(),
+        )
+        .unwrap();
 
         create_project(template_dir.path(), &dest_dir, &context).unwrap();
 
@@ -153,11 +266,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn masks_secrets_when_logging_the_context() {
+        let mut config = config("svc");
+        for (key, secret) in [("api-token", true), ("region", false)] {
+            config.env.insert(
+                key.to_string(),
+                env::EnvVarConfig::String {
+                    prompt: "?".to_string(),
+                    default: None,
+                    secret,
+                },
+            );
+        }
+        let mut context = TeraContext::new();
+        context.insert("API_TOKEN", "hunter2");
+        context.insert("REGION", "eu");
+
+        let masked = masked_context(&context, &config);
+
+        assert_eq!(masked["API_TOKEN"], "********");
+        assert_eq!(masked["REGION"], "eu");
+    }
+
     #[test]
     fn create_project_renders_and_respects_ignores() {
         let template_dir = tempfile::tempdir().unwrap();
         let dest_dir = tempfile::tempdir().unwrap().path().join("demo");
-        let hayaku = Hayaku::try_new_from_dir(tempfile::tempdir().unwrap().path()).unwrap();
 
         fs::create_dir_all(template_dir.path().join("nested")).unwrap();
         fs::create_dir_all(template_dir.path().join(".git")).unwrap();
@@ -172,7 +307,14 @@ mod tests {
         write_template(template_dir.path(), "ignored.txt", b"nope");
         write_template(template_dir.path(), ".git/config", b"secret");
 
-        let context = env::build_context("demo", &config("demo"), &hayaku).unwrap();
+        let context = env::build_context(
+            "demo",
+            &config("demo"),
+            &HayakuSettings::default(),
+            &Answers::interactive(),
+            &HostInfo::default(),
+        )
+        .unwrap();
 
         create_project(template_dir.path(), &dest_dir, &context).unwrap();
 
@@ -188,6 +330,64 @@ mod tests {
         assert!(!dest_dir.join(".git").exists());
     }
 
+    #[test]
+    fn render_project_skips_test_fixtures() {
+        let template_dir = tempfile::tempdir().unwrap();
+        write_template(template_dir.path(), ".hayaku/tests/default.toml", b"");
+        write_template(
+            template_dir.path(),
+            ".hayaku/tests/snapshots/default/a.txt",
+            b"",
+        );
+        write_template(template_dir.path(), "tests/fixture.toml", b"");
+        write_template(
+            template_dir.path(),
+            "tests/integration.rs",
+            b"// {{ PROJECT_NAME }}",
+        );
+
+        let context = env::build_context(
+            "demo",
+            &config("demo"),
+            &HayakuSettings::default(),
+            &Answers::interactive(),
+            &HostInfo::default(),
+        )
+        .unwrap();
+        let rendered = render_project(template_dir.path(), &context).unwrap();
+
+        assert_eq!(
+            rendered.into_iter().collect::<Vec<_>>(),
+            vec![
+                (PathBuf::from("tests/fixture.toml"), String::new()),
+                (PathBuf::from("tests/integration.rs"), "// demo".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn output_paths_strip_tera_extensions() {
+        let dir = tempfile::tempdir().unwrap();
+        write_template(dir.path(), "hayaku.toml", b"[template]\nname = \"t\"\n");
+        write_template(dir.path(), "[PROJECT_NAME]/main.rs.tera", b"");
+        write_template(dir.path(), "README.md", b"");
+
+        assert_eq!(
+            output_paths(dir.path()).unwrap(),
+            vec![
+                PathBuf::from("README.md"),
+                PathBuf::from("[PROJECT_NAME]/main.rs"),
+            ]
+        );
+    }
+
+    #[test]
+    fn escaped_text_renders_as_is() {
+        let text = "${{ matrix.os }} {% endraw %} {# note #} {x} {{{";
+        let rendered = tera::Tera::one_off(&escape_tera(text), &TeraContext::new(), false).unwrap();
+        assert_eq!(rendered, text);
+    }
+
     #[test]
     fn process_dest_path_substitutes_with_context() {
         let mut context = TeraContext::new();
@@ -198,4 +398,22 @@ mod tests {
 
         assert_eq!(resolved, Path::new("output/demo/config.toml"));
     }
+
+    #[test]
+    fn process_dest_path_substitutes_inside_file_names() {
+        let mut context = TeraContext::new();
+        context.insert("PROJECT_NAME", "demo");
+
+        let dest = Path::new("src/init_[PROJECT_NAME].rs");
+        assert_eq!(
+            super::process_dest_path(dest, &context),
+            Path::new("src/init_demo.rs")
+        );
+
+        let unknown = Path::new("src/[UNKNOWN]_[PROJECT_NAME].rs");
+        assert_eq!(
+            super::process_dest_path(unknown, &context),
+            Path::new("src/[UNKNOWN]_demo.rs")
+        );
+    }
 }
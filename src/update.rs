@@ -0,0 +1,179 @@
+use crate::merge;
+use crate::templating;
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What happened to a file when a project was updated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileUpdate {
+    /// The template's changes were merged cleanly
+    Merged(PathBuf),
+    /// The template added a file that the project didn't have
+    Added(PathBuf),
+    /// The template removed a file that the project hadn't changed
+    Removed(PathBuf),
+    /// Both the template and the project changed the same lines; the file now
+    /// contains conflict markers
+    Conflicted(PathBuf, usize),
+    /// The template changed a file that was deleted from the project; the new
+    /// version was written next to it as `<file>.rej`
+    Rejected(PathBuf),
+    /// The template removed a file that the project changed, so it was kept
+    Kept(PathBuf),
+}
+
+fn read_project_file(path: &Path) -> Result<Option<String>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    fs::read_to_string(path)
+        .map(Some)
+        .with_context(|| format!("Failed to read {}", path.display()))
+}
+
+fn reject_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".rej");
+    path.with_file_name(name)
+}
+
+/// A change to a single file in the project.
+enum Change {
+    Write(PathBuf, String),
+    Remove(PathBuf),
+}
+
+/// Applies the difference between two renders of a template, `old` and `new`,
+/// to the project in `project_dir`. Files the template didn't change are left
+/// alone. Every file is read and merged before anything is written, so a
+/// failure part way through doesn't leave the project half updated.
+pub fn update_project(
+    project_dir: &Path,
+    old: &BTreeMap<PathBuf, String>,
+    new: &BTreeMap<PathBuf, String>,
+) -> Result<Vec<FileUpdate>> {
+    let paths: BTreeSet<&PathBuf> = old.keys().chain(new.keys()).collect();
+    let mut updates = Vec::new();
+    let mut changes = Vec::new();
+    for rel_path in paths {
+        let path = project_dir.join(rel_path);
+        let current = read_project_file(&path)?;
+        match (old.get(rel_path), new.get(rel_path), current) {
+            (Some(old), Some(new), _) if old == new => {}
+            (_, Some(new), Some(current)) if current == *new => {}
+            (Some(_), Some(new), None) => {
+                changes.push(Change::Write(reject_path(&path), new.clone()));
+                updates.push(FileUpdate::Rejected(rel_path.clone()));
+            }
+            (None, Some(new), None) => {
+                changes.push(Change::Write(path, new.clone()));
+                updates.push(FileUpdate::Added(rel_path.clone()));
+            }
+            (old, Some(new), Some(current)) => {
+                let base = old.map(String::as_str).unwrap_or("");
+                let merge = merge::merge3(base, &current, new, "project", "template");
+                changes.push(Change::Write(path, merge.text));
+                updates.push(if merge.conflicts > 0 {
+                    FileUpdate::Conflicted(rel_path.clone(), merge.conflicts)
+                } else {
+                    FileUpdate::Merged(rel_path.clone())
+                });
+            }
+            (Some(old), None, Some(current)) => {
+                if current == *old {
+                    changes.push(Change::Remove(path));
+                    updates.push(FileUpdate::Removed(rel_path.clone()));
+                } else {
+                    updates.push(FileUpdate::Kept(rel_path.clone()));
+                }
+            }
+            (_, None, _) => {}
+        }
+    }
+
+    for change in changes {
+        match change {
+            Change::Write(path, contents) => templating::write_file(&path, &contents)?,
+            Change::Remove(path) => fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?,
+        }
+    }
+    Ok(updates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(entries: &[(&str, &str)]) -> BTreeMap<PathBuf, String> {
+        entries
+            .iter()
+            .map(|(path, contents)| (PathBuf::from(path), contents.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn applies_template_changes_to_project() {
+        let project = tempfile::tempdir().unwrap();
+        let dir = project.path();
+        fs::write(dir.join("merged.txt"), "one\ntwo\nlocal\n").unwrap();
+        fs::write(dir.join("conflict.txt"), "mine\n").unwrap();
+        fs::write(dir.join("unused.txt"), "old\n").unwrap();
+        fs::write(dir.join("edited.txt"), "edited\n").unwrap();
+        fs::write(dir.join("same.txt"), "same\n").unwrap();
+
+        let old = files(&[
+            ("merged.txt", "one\ntwo\nthree\n"),
+            ("conflict.txt", "base\n"),
+            ("unused.txt", "old\n"),
+            ("edited.txt", "old\n"),
+            ("deleted.txt", "old\n"),
+            ("same.txt", "same\n"),
+        ]);
+        let new = files(&[
+            ("merged.txt", "ONE\ntwo\nthree\n"),
+            ("conflict.txt", "template\n"),
+            ("deleted.txt", "new\n"),
+            ("added.txt", "added\n"),
+            ("same.txt", "same\n"),
+        ]);
+
+        let updates = update_project(dir, &old, &new).unwrap();
+
+        assert_eq!(
+            updates,
+            vec![
+                FileUpdate::Added(PathBuf::from("added.txt")),
+                FileUpdate::Conflicted(PathBuf::from("conflict.txt"), 1),
+                FileUpdate::Rejected(PathBuf::from("deleted.txt")),
+                FileUpdate::Kept(PathBuf::from("edited.txt")),
+                FileUpdate::Merged(PathBuf::from("merged.txt")),
+                FileUpdate::Removed(PathBuf::from("unused.txt")),
+            ]
+        );
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("merged.txt"), "ONE\ntwo\nlocal\n");
+        assert_eq!(
+            read("conflict.txt"),
+            "<<<<<<< project\nmine\n=======\ntemplate\n>>>>>>> template\n"
+        );
+        assert_eq!(read("deleted.txt.rej"), "new\n");
+        assert_eq!(read("added.txt"), "added\n");
+        assert!(!dir.join("unused.txt").exists());
+    }
+
+    #[test]
+    fn writes_nothing_when_a_file_fails() {
+        let project = tempfile::tempdir().unwrap();
+        let dir = project.path();
+        fs::write(dir.join("binary.txt"), [0xff, 0xfe]).unwrap();
+
+        let old = files(&[("binary.txt", "old\n")]);
+        let new = files(&[("added.txt", "added\n"), ("binary.txt", "new\n")]);
+
+        assert!(update_project(dir, &old, &new).is_err());
+        assert!(!dir.join("added.txt").exists());
+    }
+}
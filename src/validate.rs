@@ -0,0 +1,396 @@
+use crate::config::ConfigToml;
+use crate::env::{
+    BUILT_IN_VARIABLES, EnvVarConfig, OPTIONAL_BUILT_IN_VARIABLES, canonical_env_key, is_reserved,
+};
+use crate::templating;
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use tera::ast::{Expr, ExprVal, Node};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.file.display(), line, self.message),
+            None => write!(f, "{}: {}", self.file.display(), self.message),
+        }
+    }
+}
+
+/// Checks a template directory for problems that would otherwise only show up
+/// when running `create`. `global_env` holds the keys of the user's global
+/// variables, which templates are allowed to reference.
+pub fn validate_template(template_dir: &Path, global_env: &[String]) -> Result<Vec<Diagnostic>> {
+    let mut diagnostics = Vec::new();
+    let mut known: HashSet<String> = BUILT_IN_VARIABLES
+        .iter()
+        .chain(OPTIONAL_BUILT_IN_VARIABLES)
+        .map(|v| v.to_string())
+        .collect();
+    known.extend(global_env.iter().map(|key| canonical_env_key(key)));
+
+    let config_path = template_dir.join("hayaku.toml");
+    if config_path.exists() {
+        let content = std::fs::read_to_string(&config_path)?;
+        match toml::from_str::<ConfigToml>(&content) {
+            Ok(config) => {
+                known.extend(config.env.keys().map(|key| canonical_env_key(key)));
+                diagnostics.extend(check_config(&config_path, &content, &config));
+            }
+            Err(err) => diagnostics.push(Diagnostic {
+                file: config_path.clone(),
+                line: err.span().map(|span| line_of_offset(&content, span.start)),
+                message: format!("invalid config: {}", err.message()),
+            }),
+        }
+    }
+
+    for entry in templating::template_walker(template_dir)?.build() {
+        let entry = entry?;
+        if !entry.file_type().map(|ft| ft.is_file()).unwrap_or(false) {
+            continue;
+        }
+        let file = entry.path().to_path_buf();
+        let rel_path = file.strip_prefix(template_dir)?;
+        diagnostics.extend(check_path_variables(&file, rel_path, &known));
+
+        let bytes =
+            std::fs::read(&file).with_context(|| format!("Failed to read {}", file.display()))?;
+        // `create` can only render text files
+        let Ok(content) = String::from_utf8(bytes) else {
+            diagnostics.push(Diagnostic {
+                file,
+                line: None,
+                message: "not valid UTF-8".to_string(),
+            });
+            continue;
+        };
+        match tera::Template::new(&rel_path.to_string_lossy(), None, &content) {
+            Ok(template) => {
+                let mut used = Vec::new();
+                let mut bound = HashSet::new();
+                collect_nodes(&template.ast, &mut used, &mut bound);
+                let mut reported = HashSet::new();
+                for var in used {
+                    if known.contains(&var) || bound.contains(&var) || !reported.insert(var.clone())
+                    {
+                        continue;
+                    }
+                    diagnostics.push(Diagnostic {
+                        file: file.clone(),
+                        line: line_of_word(&content, &var),
+                        message: format!("undefined variable `{var}`"),
+                    });
+                }
+            }
+            Err(err) => {
+                let (line, message) = describe_parse_error(&err);
+                diagnostics.push(Diagnostic {
+                    file: file.clone(),
+                    line,
+                    message,
+                });
+            }
+        }
+    }
+
+    Ok(diagnostics)
+}
+
+fn check_config(config_path: &Path, content: &str, config: &ConfigToml) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    if let Some(rule) = &config.project_name
+        && rule.regex().is_err()
+    {
+        diagnostics.push(Diagnostic {
+            file: config_path.to_path_buf(),
+            line: line_of(content, "[project_name]"),
+            message: format!(
+                "project_name pattern `{}` is not a valid regular expression",
+                rule.pattern
+            ),
+        });
+    }
+    for (key, env_cfg) in config.env.iter() {
+        if is_reserved(&canonical_env_key(key)) {
+            diagnostics.push(Diagnostic {
+                file: config_path.to_path_buf(),
+                line: line_of(content, &format!("[env.{key}]")),
+                message: format!("`{key}` is reserved for hayaku's built-in variables"),
+            });
+        }
+        if let EnvVarConfig::Choices {
+            choices,
+            default: Some(default),
+            ..
+        } = env_cfg
+            && !choices.contains(default)
+        {
+            diagnostics.push(Diagnostic {
+                file: config_path.to_path_buf(),
+                line: line_of(content, &format!("[env.{key}]")),
+                message: format!(
+                    "default `{default}` for `{key}` is not one of its choices: {}",
+                    choices.join(", ")
+                ),
+            });
+        }
+    }
+    diagnostics
+}
+
+fn check_path_variables(file: &Path, rel_path: &Path, known: &HashSet<String>) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for component in rel_path.components() {
+        let component = component.as_os_str().to_string_lossy();
+        let mut rest: &str = &component;
+        while let Some(start) = rest.find('[') {
+            let Some(len) = rest[start..].find(']') else {
+                break;
+            };
+            let var = &rest[start + 1..start + len];
+            if !known.contains(var) {
+                diagnostics.push(Diagnostic {
+                    file: file.to_path_buf(),
+                    line: None,
+                    message: format!("path segment refers to unknown variable `{var}`"),
+                });
+            }
+            rest = &rest[start + len + 1..];
+        }
+    }
+    diagnostics
+}
+
+/// The root variable of an identifier such as `COMPANY.name` or `ITEMS[0]`.
+fn root_ident(ident: &str) -> &str {
+    ident.split(['.', '[']).next().unwrap_or(ident)
+}
+
+fn collect_nodes(nodes: &[Node], used: &mut Vec<String>, bound: &mut HashSet<String>) {
+    for node in nodes {
+        match node {
+            Node::VariableBlock(_, expr) => collect_expr(expr, used),
+            Node::Set(_, set) => {
+                bound.insert(set.key.clone());
+                collect_expr(&set.value, used);
+            }
+            Node::FilterSection(_, section, _) => {
+                section
+                    .filter
+                    .args
+                    .values()
+                    .for_each(|arg| collect_expr(arg, used));
+                collect_nodes(&section.body, used, bound);
+            }
+            Node::Block(_, block, _) => collect_nodes(&block.body, used, bound),
+            Node::MacroDefinition(_, definition, _) => {
+                bound.extend(definition.args.keys().cloned());
+                collect_nodes(&definition.body, used, bound);
+            }
+            Node::Forloop(_, forloop, _) => {
+                bound.insert(forloop.value.clone());
+                bound.extend(forloop.key.clone());
+                bound.insert("loop".to_string());
+                collect_expr(&forloop.container, used);
+                collect_nodes(&forloop.body, used, bound);
+                if let Some(empty_body) = &forloop.empty_body {
+                    collect_nodes(empty_body, used, bound);
+                }
+            }
+            Node::If(condition, _) => {
+                for (_, expr, body) in &condition.conditions {
+                    collect_expr(expr, used);
+                    collect_nodes(body, used, bound);
+                }
+                if let Some((_, body)) = &condition.otherwise {
+                    collect_nodes(body, used, bound);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn collect_expr(expr: &Expr, used: &mut Vec<String>) {
+    // `default` makes undefined variables safe to use
+    if expr.has_default_filter() {
+        return;
+    }
+    for filter in &expr.filters {
+        filter.args.values().for_each(|arg| collect_expr(arg, used));
+    }
+    match &expr.val {
+        ExprVal::Ident(ident) => used.push(root_ident(ident).to_string()),
+        ExprVal::Math(math) => {
+            collect_expr(&math.lhs, used);
+            collect_expr(&math.rhs, used);
+        }
+        ExprVal::Logic(logic) => {
+            collect_expr(&logic.lhs, used);
+            collect_expr(&logic.rhs, used);
+        }
+        ExprVal::Test(test) => {
+            if test.name != "defined" && test.name != "undefined" {
+                used.push(root_ident(&test.ident).to_string());
+            }
+            test.args.iter().for_each(|arg| collect_expr(arg, used));
+        }
+        ExprVal::MacroCall(call) => call.args.values().for_each(|arg| collect_expr(arg, used)),
+        ExprVal::FunctionCall(call) => call.args.values().for_each(|arg| collect_expr(arg, used)),
+        ExprVal::Array(items) => items.iter().for_each(|item| collect_expr(item, used)),
+        ExprVal::StringConcat(concat) => {
+            for value in &concat.values {
+                if let ExprVal::Ident(ident) = value {
+                    used.push(root_ident(ident).to_string());
+                }
+            }
+        }
+        ExprVal::In(in_expr) => {
+            collect_expr(&in_expr.lhs, used);
+            collect_expr(&in_expr.rhs, used);
+        }
+        ExprVal::String(_) | ExprVal::Int(_) | ExprVal::Float(_) | ExprVal::Bool(_) => {}
+    }
+}
+
+/// Pulls the line number and the "expected ..." summary out of a Tera parse
+/// error, which embeds a pest error report.
+fn describe_parse_error(err: &tera::Error) -> (Option<usize>, String) {
+    let report = err.to_string();
+    let line = report.lines().find_map(|l| {
+        let position = l.trim_start().strip_prefix("--> ")?;
+        position.split(':').next()?.parse().ok()
+    });
+    let summary = report
+        .lines()
+        .rev()
+        .find_map(|l| l.trim_start().strip_prefix("= "))
+        .unwrap_or(&report);
+    (line, format!("template does not compile: {summary}"))
+}
+
+fn line_of_offset(content: &str, offset: usize) -> usize {
+    content[..offset.min(content.len())].matches('\n').count() + 1
+}
+
+fn line_of(content: &str, needle: &str) -> Option<usize> {
+    content
+        .lines()
+        .position(|line| line.contains(needle))
+        .map(|i| i + 1)
+}
+
+/// The first line that contains `word` surrounded by non-identifier characters.
+fn line_of_word(content: &str, word: &str) -> Option<usize> {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    content
+        .lines()
+        .position(|line| {
+            line.match_indices(word).any(|(i, _)| {
+                let before = line[..i].chars().next_back();
+                let after = line[i + word.len()..].chars().next();
+                !before.is_some_and(is_ident) && !after.is_some_and(is_ident)
+            })
+        })
+        .map(|i| i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write(dir: &Path, rel: &str, contents: &str) {
+        let path = dir.join(rel);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn valid_template_has_no_diagnostics() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "hayaku.toml",
+            "[template]\nname = \"ok\"\n\n[env.crate_type]\ntype = \"choices\"\nprompt = \"?\"\nchoices = [\"lib\", \"bin\"]\ndefault = \"bin\"\n",
+        );
+        write(
+            dir.path(),
+            "[PROJECT_NAME]/Cargo.toml.tera",
+            "name = \"{{ PROJECT_NAME }}\"\nauthor = \"{{ AUTHOR | default(value='') }}\"\n{% if CRATE_TYPE == \"lib\" %}[lib]{% endif %}\n{% for f in [1, 2] %}{{ f }}{{ loop.index }}{% endfor %}\n{{ LICENSE }}\n",
+        );
+
+        let diagnostics = validate_template(dir.path(), &["license".to_string()]).unwrap();
+        assert_eq!(diagnostics, vec![]);
+    }
+
+    #[test]
+    fn reports_problems_with_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "hayaku.toml",
+            "[template]\nname = \"bad\"\n\n[env.kind]\ntype = \"choices\"\nprompt = \"?\"\nchoices = [\"a\", \"b\"]\ndefault = \"c\"\n\n[env.hayaku]\ntype = \"string\"\nprompt = \"?\"\n",
+        );
+        write(dir.path(), "broken.txt", "line one\n{% if %}\n");
+        write(dir.path(), "undefined.txt", "ok\n\n{{ MISSING.field }}\n");
+        write(dir.path(), "[NOPE].txt", "");
+        fs::write(dir.path().join("logo.png"), [0x89, b'P', b'N', b'G', 0xff]).unwrap();
+
+        let diagnostics = validate_template(dir.path(), &[]).unwrap();
+        let lines: Vec<(String, Option<usize>)> = diagnostics
+            .iter()
+            .map(|d| {
+                (
+                    d.file.file_name().unwrap().to_string_lossy().to_string(),
+                    d.line,
+                )
+            })
+            .collect();
+
+        assert_eq!(diagnostics.len(), 6, "{diagnostics:#?}");
+        assert!(lines.contains(&("hayaku.toml".to_string(), Some(4))));
+        assert!(lines.contains(&("hayaku.toml".to_string(), Some(10))));
+        assert!(lines.contains(&("broken.txt".to_string(), Some(2))));
+        assert!(lines.contains(&("undefined.txt".to_string(), Some(3))));
+        assert!(lines.contains(&("[NOPE].txt".to_string(), None)));
+        assert!(
+            diagnostics
+                .iter()
+                .any(|d| d.file.ends_with("logo.png") && d.message == "not valid UTF-8")
+        );
+    }
+
+    #[test]
+    fn reports_unparseable_config() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "hayaku.toml", "[template]\nnam = \"x\"\n");
+
+        let diagnostics = validate_template(dir.path(), &[]).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.starts_with("invalid config"));
+    }
+
+    #[test]
+    fn reports_invalid_project_name_pattern() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "hayaku.toml",
+            "[template]\nname = \"x\"\n\n[project_name]\npattern = \"[a-z\"\n",
+        );
+
+        let diagnostics = validate_template(dir.path(), &[]).unwrap();
+        assert_eq!(diagnostics.len(), 1, "{diagnostics:#?}");
+        assert_eq!(diagnostics[0].line, Some(4));
+    }
+}